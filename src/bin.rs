@@ -1,20 +1,389 @@
-use browsercookie::{Attribute, Browser, CookieFinder};
-use clap::{App, Arg};
+use browsercookie::keyring::{CommandPassword, EnvPassword};
+use browsercookie::{
+    json, netscape, Attribute, Browser, BrowserCookie, BrowserCookies, CookieFinder,
+    CookieFinderBuilder, MergePolicy,
+};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use regex::Regex;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use strum::IntoEnumIterator;
+use url::Url;
 
 #[macro_use]
 extern crate clap;
 
-async fn curl_output<'a>(cookie_finder: &CookieFinder<'a>) {
-    let cookie_jar = cookie_finder.find().await;
-    let cookie = cookie_jar.iter().last().expect("Cookie not found");
-    print!("Cookie: {}", cookie);
+// Longer values are cut in the list table
+const MAX_VALUE_WIDTH: usize = 40;
+
+fn exit_with_error(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+// Arguments selecting where cookies are read from and which ones are kept,
+// shared by every subcommand
+fn source_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("browser")
+            .short("b")
+            .long("browser")
+            .value_name("BROWSER")
+            .multiple(true)
+            .number_of_values(1)
+            .help(
+                "Reads this browser, every installed one by default. Accepted values: firefox, \
+                 chrome, chrome-beta, chrome-dev, chrome-canary, chromium, brave, edge, vivaldi, \
+                 opera",
+            )
+            .takes_value(true),
+        Arg::with_name("profile")
+            .short("p")
+            .long("profile")
+            .value_name("PROFILE")
+            .multiple(true)
+            .number_of_values(1)
+            .conflicts_with("all-profiles")
+            .help("Reads the profile with this name instead of the default one")
+            .takes_value(true),
+        Arg::with_name("all-profiles")
+            .long("all-profiles")
+            .help("Reads every profile instead of the default one"),
+        Arg::with_name("snapshot").long("snapshot").help(
+            "Reads a private copy of each cookie database, including the latest cookies of \
+                 a running browser",
+        ),
+        Arg::with_name("merge-policy")
+            .long("merge-policy")
+            .value_name("POLICY")
+            .possible_values(&[
+                "newest-wins",
+                "prefer-persistent",
+                "prefer-session",
+                "keep-all",
+            ])
+            .help("Picks the cookie kept when several sources have the same one")
+            .takes_value(true),
+        Arg::with_name("key-command")
+            .long("key-command")
+            .value_name("COMMAND")
+            .help(
+                "Runs this command for the Chromium Safe Storage password, e.g. \"secret-tool \
+                 lookup application {application}\", {application} being the browser's \
+                 keyring entry",
+            )
+            .takes_value(true),
+        Arg::with_name("key-env")
+            .long("key-env")
+            .value_name("VARIABLE")
+            .help("Reads the Chromium Safe Storage password from this environment variable")
+            .takes_value(true),
+        Arg::with_name("container")
+            .short("c")
+            .long("container")
+            .value_name("CONTAINER")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Keeps cookies of the Firefox container with this name")
+            .takes_value(true),
+        Arg::with_name("cookie-file")
+            .short("f")
+            .long("cookie-file")
+            .value_name("COOKIES_TXT")
+            .multiple(true)
+            .number_of_values(1)
+            .help(
+                "Reads a Netscape cookies.txt file, instead of browsers unless --browser is given",
+            )
+            .takes_value(true),
+        Arg::with_name("domain")
+            .short("d")
+            .long("domain")
+            .value_name("DOMAIN_REGEX")
+            .help("Keeps cookies whose domain matches")
+            .takes_value(true),
+        Arg::with_name("name")
+            .short("n")
+            .long("name")
+            .value_name("NAME_REGEX")
+            .help("Keeps cookies whose name matches")
+            .takes_value(true),
+        Arg::with_name("path")
+            .long("path")
+            .value_name("PATH_REGEX")
+            .help("Keeps cookies whose path matches")
+            .takes_value(true),
+    ]
+}
+
+fn parse_browsers(matches: &ArgMatches) -> Vec<Browser> {
+    matches
+        .values_of("browser")
+        .map(|browsers| {
+            browsers
+                .map(|b| {
+                    Browser::from_str(b)
+                        .unwrap_or_else(|_| exit_with_error(format!("Unknown browser: {}", b)))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse_regex(matches: &ArgMatches, name: &str) -> Option<Regex> {
+    let regex = matches.value_of(name)?;
+    Some(Regex::new(regex).unwrap_or_else(|e| exit_with_error(format!("--{}: {}", name, e))))
+}
+
+fn builder<'a>(matches: &ArgMatches, cookie_files: &'a [PathBuf]) -> CookieFinderBuilder<'a> {
+    let mut builder = CookieFinder::builder();
+    for browser in parse_browsers(matches) {
+        builder = builder.with_browser(browser);
+    }
+    for profile in matches.values_of("profile").into_iter().flatten() {
+        builder = builder.with_profile(profile);
+    }
+    if matches.is_present("all-profiles") {
+        builder = builder.with_all_profiles();
+    }
+    if let Some(merge_policy) = matches.value_of("merge-policy") {
+        builder = builder.with_merge_policy(MergePolicy::from_str(merge_policy).unwrap());
+    }
+    if matches.is_present("snapshot") {
+        builder = builder.with_snapshot();
+    }
+    if let Some(command) = matches.value_of("key-command") {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .unwrap_or_else(|| exit_with_error("--key-command: empty command"));
+        let args: Vec<&str> = words.collect();
+        builder = builder.with_key_provider(CommandPassword::new(program, &args));
+    }
+    if let Some(variable) = matches.value_of("key-env") {
+        builder = builder.with_key_provider(EnvPassword::new(variable));
+    }
+    for container in matches.values_of("container").into_iter().flatten() {
+        builder = builder.with_container(container);
+    }
+    for path in cookie_files {
+        builder = builder.with_netscape_file(path);
+    }
+    for (name, attribute) in [
+        ("domain", Attribute::Domain),
+        ("name", Attribute::Name),
+        ("path", Attribute::Path),
+    ] {
+        if let Some(regex) = parse_regex(matches, name) {
+            builder = builder.with_regexp(regex, attribute);
+        }
+    }
+    builder
+}
+
+fn cookie_files(matches: &ArgMatches) -> Vec<PathBuf> {
+    matches
+        .values_of("cookie-file")
+        .map(|files| files.map(PathBuf::from).collect())
+        .unwrap_or_default()
+}
+
+// Sources that can't be read are reported, the others still print their cookies
+async fn find_cookies(finder: &CookieFinder<'_>) -> BrowserCookies {
+    let (cookies, errors) = finder.find_with_errors().await;
+    for error in errors {
+        eprintln!("warning: {}", error);
+    }
+    cookies
+}
+
+async fn find_matching_cookies(matches: &ArgMatches<'_>) -> BrowserCookies {
+    let cookie_files = cookie_files(matches);
+    find_cookies(&builder(matches, &cookie_files).build()).await
+}
+
+fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let header: Vec<String> = header.iter().map(|h| String::from(*h)).collect();
+    for row in std::iter::once(&header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+fn origin(cookie: &BrowserCookie) -> (String, String) {
+    match cookie.browser() {
+        Some(browser) => (
+            browser.to_string(),
+            String::from(cookie.profile().unwrap_or("")),
+        ),
+        None => (
+            String::from("file"),
+            cookie
+                .source_file()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default(),
+        ),
+    }
+}
+
+fn browsers(matches: &ArgMatches) {
+    let finder = CookieFinder::builder().build();
+    let selected = parse_browsers(matches);
+    let rows: Vec<Vec<String>> = Browser::iter()
+        .filter(|b| selected.is_empty() || selected.contains(b))
+        .filter_map(|browser| {
+            let profiles = finder.profiles(&browser).ok()?;
+            Some(vec![browser.to_string(), profiles.len().to_string()])
+        })
+        .collect();
+    print_table(&["BROWSER", "PROFILES"], &rows);
+}
+
+fn profiles(matches: &ArgMatches) {
+    let finder = CookieFinder::builder().build();
+    let selected = parse_browsers(matches);
+    let mut rows = vec![];
+    for browser in Browser::iter().filter(|b| selected.is_empty() || selected.contains(b)) {
+        match finder.profiles(&browser) {
+            Ok(profiles) => rows.extend(profiles.into_iter().map(|p| {
+                vec![
+                    browser.to_string(),
+                    p.name,
+                    String::from(if p.is_default { "yes" } else { "" }),
+                    p.path.display().to_string(),
+                ]
+            })),
+            // Browsers asked for by name should be installed
+            Err(e) if !selected.is_empty() => exit_with_error(e),
+            Err(_) => (),
+        }
+    }
+    print_table(&["BROWSER", "PROFILE", "DEFAULT", "PATH"], &rows);
+}
+
+async fn list(matches: &ArgMatches<'_>) {
+    let cookies = find_matching_cookies(matches).await;
+    let rows: Vec<Vec<String>> = cookies
+        .iter()
+        .map(|c| {
+            let (browser, profile) = origin(c);
+            let mut value: String = c.value().chars().take(MAX_VALUE_WIDTH).collect();
+            if value.len() < c.value().len() {
+                value.push('…');
+            }
+            vec![
+                browser,
+                profile,
+                String::from(c.container().unwrap_or("")),
+                String::from(c.domain().unwrap_or("")),
+                String::from(c.path().unwrap_or("")),
+                String::from(c.name()),
+                value,
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            "BROWSER",
+            "PROFILE",
+            "CONTAINER",
+            "DOMAIN",
+            "PATH",
+            "NAME",
+            "VALUE",
+        ],
+        &rows,
+    );
 }
 
-async fn python_output<'a>(cookie_finder: &CookieFinder<'a>) {
-    let cookie_jar = cookie_finder.find().await;
-    let cookie = cookie_jar.iter().last().expect("Cookie not found");
-    print!("{{'Cookie': '{}'}}", cookie);
+async fn get(matches: &ArgMatches<'_>) {
+    let cookie_name = matches.value_of("cookie").unwrap();
+    let cookies = find_matching_cookies(matches).await;
+    let found: Vec<&BrowserCookie> = cookies.get_all(cookie_name).collect();
+    match found.as_slice() {
+        [] => exit_with_error(format!("No cookie named {} found", cookie_name)),
+        [first, rest @ ..] if rest.iter().all(|c| c.value() == first.value()) => {
+            println!("{}", first.value())
+        }
+        _ => {
+            let candidates: Vec<String> = found
+                .iter()
+                .map(|c| {
+                    let (browser, profile) = origin(c);
+                    format!(
+                        "  {} {} {}{}{}{}",
+                        browser,
+                        profile,
+                        c.container()
+                            .map(|c| format!("[{}] ", c))
+                            .unwrap_or_default(),
+                        c.domain().unwrap_or(""),
+                        c.path().unwrap_or(""),
+                        c.partition_key()
+                            .map(|k| format!(" under {}", k))
+                            .unwrap_or_default()
+                    )
+                })
+                .collect();
+            exit_with_error(format!(
+                "{} cookies named {} have different values, narrow down with --domain, \
+                 --browser, --profile or --container:\n{}",
+                found.len(),
+                cookie_name,
+                candidates.join("\n")
+            ))
+        }
+    }
+}
+
+async fn header(matches: &ArgMatches<'_>) {
+    let url = matches.value_of("url").unwrap();
+    let url = Url::parse(url).unwrap_or_else(|e| exit_with_error(format!("{}: {}", url, e)));
+    let top_level = match matches.value_of("top-level-url") {
+        Some(top_level) => Url::parse(top_level)
+            .unwrap_or_else(|e| exit_with_error(format!("{}: {}", top_level, e))),
+        None => url.clone(),
+    };
+    let header = find_matching_cookies(matches)
+        .await
+        .header_for_url_under(&url, &top_level);
+    if header.is_empty() {
+        exit_with_error(format!("No cookie found for {}", url));
+    }
+    match matches.value_of("format").unwrap() {
+        "curl" => println!("Cookie: {}", header),
+        "python" => println!("{{'Cookie': '{}'}}", header),
+        _ => println!("{}", header),
+    }
+}
+
+async fn export(matches: &ArgMatches<'_>) {
+    let cookies = find_matching_cookies(matches).await;
+    let mut writer: Box<dyn Write> = match matches.value_of("output") {
+        Some(path) => Box::new(File::create(path).unwrap_or_else(|e| exit_with_error(e))),
+        None => Box::new(io::stdout()),
+    };
+    let result = match matches.value_of("format").unwrap() {
+        "json" => json::write_json(&cookies, &mut writer),
+        "jsonl" => json::write_jsonl(&cookies, &mut writer),
+        _ => netscape::write(&cookies, &mut writer),
+    };
+    result
+        .and_then(|_| writer.flush())
+        .unwrap_or_else(|e| exit_with_error(e));
 }
 
 #[tokio::main]
@@ -23,66 +392,97 @@ async fn main() {
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
-        .arg(
-            Arg::with_name("domain")
-                .short("d")
-                .long("domain")
-                .value_name("DOMAIN_REGEX")
-                .required(true)
-                .help("Sets a domain filter for cookies")
-                .takes_value(true),
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("browsers")
+                .about("Lists the installed browsers")
+                .args(&source_args()[..1]),
+        )
+        .subcommand(
+            SubCommand::with_name("profiles")
+                .about("Lists the profiles of installed browsers")
+                .args(&source_args()[..1]),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Prints a table of the matching cookies")
+                .args(&source_args()),
         )
-        .arg(
-            Arg::with_name("browser")
-                .short("b")
-                .long("browser")
-                .value_name("BROWSER")
-                .multiple(true)
-                .default_value("firefox")
-                .help("Accepted values: firefox (only one can be provided)")
-                .takes_value(true),
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Prints the value of a cookie")
+                .args(&source_args())
+                .arg(
+                    Arg::with_name("cookie")
+                        .value_name("COOKIE_NAME")
+                        .required(true)
+                        .help("Name of the cookie"),
+                ),
         )
-        .arg(
-            Arg::with_name("name")
-                .short("n")
-                .long("name")
-                .conflicts_with("output")
-                .value_name("COOKIE_NAME")
-                .help("Specify a cookie name to output only that value")
-                .takes_value(true),
+        .subcommand(
+            SubCommand::with_name("header")
+                .about("Prints the Cookie header a browser would send to a URL")
+                .args(&source_args())
+                .arg(
+                    Arg::with_name("url")
+                        .short("u")
+                        .long("url")
+                        .value_name("URL")
+                        .required(true)
+                        .help("URL the request goes to")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("top-level-url")
+                        .long("top-level-url")
+                        .value_name("URL")
+                        .help(
+                            "URL of the page embedding the request, for partitioned cookies. \
+                             Defaults to --url",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["curl", "python", "value"])
+                        .default_value("curl")
+                        .help("Prints a curl header, a python headers dict or only the value")
+                        .takes_value(true),
+                ),
         )
-        .arg(
-            Arg::with_name("output")
-                .short("o")
-                .long("output")
-                .value_name("OUTPUT_FORMAT")
-                .help("Accepted values: curl,python (only one can be provided)")
-                .default_value("curl")
-                .takes_value(true),
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Writes the matching cookies to a cookie jar file")
+                .args(&source_args())
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["netscape", "json", "jsonl"])
+                        .default_value("netscape")
+                        .help("Netscape cookies.txt, a JSON document or JSON Lines")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Writes to this file instead of the standard output")
+                        .takes_value(true),
+                ),
         )
         .get_matches();
 
-    let domain_regex = Regex::new(matches.value_of("domain").unwrap()).unwrap();
-
-    let mut builder = CookieFinder::builder().with_regexp(domain_regex, Attribute::Domain);
-
-    for b in matches.values_of("browser").unwrap() {
-        if b == "firefox" {
-            builder = builder.with_browser(Browser::Firefox);
-        }
-    }
-
-    if let Some(cookie_name) = matches.value_of("name") {
-        builder.build().find().await.iter().for_each(|c| {
-            if c.name() == cookie_name {
-                println!("{}", c.value());
-            }
-        });
-    } else {
-        match matches.value_of("output").unwrap() {
-            "curl" => curl_output(&builder.build()).await,
-            "python" => python_output(&builder.build()).await,
-            _ => (),
-        }
+    match matches.subcommand() {
+        ("browsers", Some(matches)) => browsers(matches),
+        ("profiles", Some(matches)) => profiles(matches),
+        ("list", Some(matches)) => list(matches).await,
+        ("get", Some(matches)) => get(matches).await,
+        ("header", Some(matches)) => header(matches).await,
+        ("export", Some(matches)) => export(matches).await,
+        _ => (),
     }
 }
@@ -0,0 +1,867 @@
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use cookie::time::{Duration, OffsetDateTime};
+use cookie::{Cookie, SameSite};
+#[allow(unused_imports)]
+use dirs::config_dir;
+use futures::TryStreamExt;
+use pbkdf2::pbkdf2_hmac;
+use serde_json::Value;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::prelude::*;
+use sqlx::SqliteConnection;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::cookies::{BrowserCookie, BrowserCookies, PartitionKey};
+use crate::errors::BrowsercookieError;
+use crate::keyring::KeyProvider;
+use crate::snapshot;
+use crate::{Browser, Profile};
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+// Chromium on Linux falls back to this password when no keyring is available
+const LINUX_FALLBACK_PASSWORD: &[u8] = b"peanuts";
+const SALT: &[u8] = b"saltysalt";
+const IV: [u8; 16] = [b' '; 16];
+
+const DEFAULT_PROFILE: &str = "Default";
+
+// Seconds between 1601-01-01 and 1970-01-01
+const WINDOWS_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+// Since this meta version the plaintext is prefixed with SHA256(host_key)
+const HOST_DIGEST_META_VERSION: i64 = 24;
+const HOST_DIGEST_LEN: usize = 32;
+
+/// Where a Chromium based browser keeps its data and its Safe Storage password
+struct Flavor {
+    // Relative to the user's config directory
+    config_dir: &'static str,
+    // Application attribute of the "<Name> Safe Storage" keyring entry
+    keyring_application: &'static str,
+    // Opera keeps a single profile right in its config directory
+    has_profiles: bool,
+}
+
+fn get_flavor(browser: &Browser) -> Option<Flavor> {
+    let (config_dir, keyring_application) = match browser {
+        Browser::Chrome => ("google-chrome", "chrome"),
+        Browser::ChromeBeta => ("google-chrome-beta", "chrome"),
+        Browser::ChromeDev => ("google-chrome-unstable", "chrome"),
+        Browser::ChromeCanary => ("google-chrome-canary", "chrome"),
+        Browser::Chromium => ("chromium", "chromium"),
+        Browser::Brave => ("BraveSoftware/Brave-Browser", "brave"),
+        Browser::Edge => ("microsoft-edge", "chromium"),
+        Browser::Vivaldi => ("vivaldi", "chrome"),
+        Browser::Opera => ("opera", "chromium"),
+        Browser::Firefox => return None,
+    };
+    Some(Flavor {
+        config_dir,
+        keyring_application,
+        has_profiles: *browser != Browser::Opera,
+    })
+}
+
+#[cfg(test)]
+fn get_config_home() -> Option<PathBuf> {
+    // Only used for tests, should do this a better way by mocking
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests/resources");
+    Some(path)
+}
+
+#[cfg(not(test))]
+fn get_config_home() -> Option<PathBuf> {
+    config_dir()
+}
+
+fn get_profiles(browser: &Browser, config_root: &Path) -> Result<Vec<Profile>, BrowsercookieError> {
+    // Profiles are listed with their display names under profile.info_cache
+    // of the Local State file, the one opened last being profile.last_used.
+    let local_state_path = config_root.join("Local State");
+    if !local_state_path.exists() {
+        let path = config_root.join(DEFAULT_PROFILE);
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        return Ok(vec![Profile {
+            name: String::from(DEFAULT_PROFILE),
+            path,
+            is_default: true,
+            install_hash: None,
+        }]);
+    }
+
+    let local_state_bytes =
+        fs::read(&local_state_path).map_err(|source| BrowsercookieError::Io {
+            browser: Some(*browser),
+            path: local_state_path.clone(),
+            source,
+        })?;
+    let local_state: Value =
+        serde_json::from_slice(&local_state_bytes).map_err(|source| BrowsercookieError::Json {
+            browser: *browser,
+            path: local_state_path.clone(),
+            source,
+        })?;
+
+    let last_used = local_state["profile"]["last_used"]
+        .as_str()
+        .unwrap_or(DEFAULT_PROFILE);
+    let info_cache = local_state["profile"]["info_cache"]
+        .as_object()
+        .ok_or_else(|| BrowsercookieError::InvalidProfile {
+            browser: *browser,
+            path: local_state_path.clone(),
+            reason: String::from("no profile list in Local State"),
+        })?;
+
+    Ok(info_cache
+        .iter()
+        .map(|(directory, info)| Profile {
+            name: String::from(info["name"].as_str().unwrap_or(directory)),
+            path: config_root.join(directory),
+            is_default: directory == last_used,
+            install_hash: None,
+        })
+        .collect())
+}
+
+fn get_cookies_path(profile_path: &Path) -> Option<PathBuf> {
+    // Newer versions moved the database into the Network directory
+    ["Network/Cookies", "Cookies"]
+        .iter()
+        .map(|p| profile_path.join(p))
+        .find(|p| p.exists())
+}
+
+fn derive_key(password: &[u8]) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    pbkdf2_hmac::<Sha1>(password, SALT, 1, &mut key);
+    key
+}
+
+struct Keys {
+    v10: [u8; 16],
+    // Derived on the first v11 value, asking the providers may run a command
+    v11: Option<Vec<[u8; 16]>>,
+    key_providers: Vec<Arc<dyn KeyProvider>>,
+    keyring_application: &'static str,
+}
+
+impl Keys {
+    fn new(key_providers: &[Arc<dyn KeyProvider>], keyring_application: &'static str) -> Self {
+        Keys {
+            v10: derive_key(LINUX_FALLBACK_PASSWORD),
+            v11: None,
+            key_providers: key_providers.to_vec(),
+            keyring_application,
+        }
+    }
+
+    async fn resolve_v11(&mut self) {
+        if self.v11.is_some() {
+            return;
+        }
+        // Providers may block, e.g. on a keyring command, so they are asked
+        // off the async worker threads
+        let key_providers = self.key_providers.clone();
+        let keyring_application = self.keyring_application;
+        let passwords = tokio::task::spawn_blocking(move || {
+            key_providers
+                .iter()
+                // A provider that panics knows no password, the others still count
+                .filter_map(|provider| {
+                    panic::catch_unwind(AssertUnwindSafe(|| provider.password(keyring_application)))
+                        .ok()
+                        .flatten()
+                })
+                .collect::<Vec<String>>()
+        })
+        .await
+        .unwrap_or_default();
+
+        // Every password offered by the providers is a candidate for v11 values,
+        // followed by the empty password Chromium uses when the keyring is unreachable.
+        let mut v11: Vec<[u8; 16]> = passwords
+            .iter()
+            .map(|password| derive_key(password.as_bytes()))
+            .collect();
+        v11.push(derive_key(b""));
+        self.v11 = Some(v11);
+    }
+}
+
+fn decrypt_with_key(
+    ciphertext: &[u8],
+    key: &[u8; 16],
+    host: &str,
+    has_host_digest: bool,
+) -> Option<String> {
+    let mut buffer = ciphertext.to_vec();
+    let plaintext = Aes128CbcDec::new(key.into(), &IV.into())
+        .decrypt_padded_mut::<Pkcs7>(&mut buffer)
+        .ok()?;
+
+    let plaintext = if has_host_digest {
+        if plaintext.len() < HOST_DIGEST_LEN {
+            return None;
+        }
+        let (digest, value) = plaintext.split_at(HOST_DIGEST_LEN);
+        // Also tells us whether a candidate key was the right one
+        if digest != &Sha256::digest(host.as_bytes())[..] {
+            return None;
+        }
+        value
+    } else {
+        plaintext
+    };
+    String::from_utf8(plaintext.to_vec()).ok()
+}
+
+fn decrypt_value(
+    encrypted_value: &[u8],
+    keys: &Keys,
+    host: &str,
+    has_host_digest: bool,
+) -> Option<String> {
+    let (candidates, ciphertext) = if let Some(c) = encrypted_value.strip_prefix(b"v10") {
+        (std::slice::from_ref(&keys.v10), c)
+    } else if let Some(c) = encrypted_value.strip_prefix(b"v11") {
+        (keys.v11.as_deref().unwrap_or_default(), c)
+    } else {
+        return None;
+    };
+
+    candidates
+        .iter()
+        .find_map(|key| decrypt_with_key(ciphertext, key, host, has_host_digest))
+}
+
+fn get_time(microseconds: i64) -> Option<OffsetDateTime> {
+    // Chromium counts microseconds since 1601-01-01, 0 meaning unset
+    if microseconds == 0 {
+        return None;
+    }
+    OffsetDateTime::from_unix_timestamp(-WINDOWS_EPOCH_OFFSET)
+        .ok()?
+        .checked_add(Duration::microseconds(microseconds))
+}
+
+fn get_same_site(same_site: i64) -> Option<SameSite> {
+    // net::CookieSameSite, -1 being unspecified
+    match same_site {
+        0 => Some(SameSite::None),
+        1 => Some(SameSite::Lax),
+        2 => Some(SameSite::Strict),
+        _ => None,
+    }
+}
+
+async fn get_meta_version(
+    browser: &Browser,
+    sqlite_path: &Path,
+    conn: &mut SqliteConnection,
+) -> Result<i64, BrowsercookieError> {
+    let version: Option<String> = sqlx::query("SELECT value FROM meta WHERE key = 'version'")
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| BrowsercookieError::from_sqlx(*browser, sqlite_path, e))?
+        .map(|row| row.get(0));
+    let unsupported = |version: &str| BrowsercookieError::UnsupportedSchema {
+        browser: *browser,
+        path: PathBuf::from(sqlite_path),
+        version: String::from(version),
+    };
+    let version = version.ok_or_else(|| unsupported("none"))?;
+    version.parse().map_err(|_| unsupported(&version))
+}
+
+async fn get_columns(
+    browser: &Browser,
+    sqlite_path: &Path,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<String>, BrowsercookieError> {
+    let rows = sqlx::query("SELECT name FROM pragma_table_info('cookies')")
+        .fetch_all(conn)
+        .await
+        .map_err(|e| BrowsercookieError::from_sqlx(*browser, sqlite_path, e))?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+fn get_partition_key(top_frame_site_key: &str, cross_site_ancestor: bool) -> Option<PartitionKey> {
+    // The top-level site is serialized as "https://example.com", empty for
+    // unpartitioned cookies
+    if top_frame_site_key.is_empty() {
+        return None;
+    }
+    let (scheme, site) = match top_frame_site_key.split_once("://") {
+        Some((scheme, site)) => (Some(String::from(scheme)), site),
+        None => (None, top_frame_site_key),
+    };
+    Some(PartitionKey {
+        scheme,
+        site: String::from(site),
+        cross_site_ancestor: Some(cross_site_ancestor),
+    })
+}
+
+async fn load_from_sqlite(
+    browser: &Browser,
+    sqlite_path: &Path,
+    cookies: &mut BrowserCookies,
+    keys: &mut Keys,
+    snapshot: bool,
+) -> Result<(), BrowsercookieError> {
+    let mut database = snapshot::open(browser, sqlite_path, snapshot).await?;
+    let result = read_cookies(browser, &mut database.conn, sqlite_path, cookies, keys).await;
+    database.close().await;
+    result
+}
+
+async fn read_cookies(
+    browser: &Browser,
+    conn: &mut SqliteConnection,
+    sqlite_path: &Path,
+    cookies: &mut BrowserCookies,
+    keys: &mut Keys,
+) -> Result<(), BrowsercookieError> {
+    let db_error = |e| BrowsercookieError::from_sqlx(*browser, sqlite_path, e);
+    let has_host_digest =
+        get_meta_version(browser, sqlite_path, conn).await? >= HOST_DIGEST_META_VERSION;
+
+    // Partitioned cookies came with later schema versions
+    let columns = get_columns(browser, sqlite_path, conn).await?;
+    let column_or = |column: &'static str, default: &'static str| {
+        if columns.iter().any(|c| c == column) {
+            column
+        } else {
+            default
+        }
+    };
+    let sql = format!(
+        "SELECT host_key, name, value, encrypted_value, path, is_secure, is_httponly, \
+         creation_utc, last_access_utc, expires_utc, samesite, {}, {} FROM cookies",
+        column_or("top_frame_site_key", "''"),
+        column_or("has_cross_site_ancestor", "0"),
+    );
+    // Only constant column names are formatted into the query
+    let mut query = sqlx::query(sqlx::AssertSqlSafe(sql)).fetch(conn);
+
+    let mut decrypted_count = 0;
+    let mut undecryptable_count = 0;
+    let mut undecryptable_v11_count = 0;
+    while let Some(row) = query.try_next().await.map_err(db_error)? {
+        let host: String = row.get(0);
+        let name: String = row.get(1);
+        let mut value: String = row.get(2);
+        let encrypted_value: Vec<u8> = row.get(3);
+        let path: String = row.get(4);
+        let secure: bool = row.get(5);
+        let http_only: bool = row.get(6);
+        let creation_time: i64 = row.get(7);
+        let last_accessed: i64 = row.get(8);
+        let expires: i64 = row.get(9);
+        let same_site: i64 = row.get(10);
+        let top_frame_site_key: String = row.get(11);
+        let cross_site_ancestor: bool = row.get(12);
+
+        if value.is_empty() && !encrypted_value.is_empty() {
+            let is_v11 = encrypted_value.starts_with(b"v11");
+            if is_v11 {
+                keys.resolve_v11().await;
+            }
+            // Values we have no key for are skipped rather than returned as ciphertext
+            match decrypt_value(&encrypted_value, keys, &host, has_host_digest) {
+                Some(decrypted) => {
+                    value = decrypted;
+                    decrypted_count += 1;
+                }
+                None => {
+                    undecryptable_count += 1;
+                    if is_v11 {
+                        undecryptable_v11_count += 1;
+                    }
+                    continue;
+                }
+            }
+        }
+
+        let host_only = !host.starts_with('.');
+        let mut cookie = Cookie::build((name, value))
+            .domain(host)
+            .path(path)
+            .secure(secure)
+            .http_only(http_only);
+        if let Some(expires) = get_time(expires) {
+            cookie = cookie.expires(expires);
+        }
+        if let Some(same_site) = get_same_site(same_site) {
+            cookie = cookie.same_site(same_site);
+        }
+        let mut browser_cookie = BrowserCookie::new(cookie.build());
+        browser_cookie.host_only = host_only;
+        browser_cookie.creation_time = get_time(creation_time);
+        browser_cookie.last_accessed = get_time(last_accessed);
+        browser_cookie.partition_key = get_partition_key(&top_frame_site_key, cross_site_ancestor);
+        cookies.add(browser_cookie);
+    }
+
+    // Some undecryptable values are expected, none decrypting means a missing key
+    if decrypted_count == 0 && undecryptable_count > 0 {
+        return Err(BrowsercookieError::Decryption {
+            browser: *browser,
+            path: PathBuf::from(sqlite_path),
+            reason: format!(
+                "none of the {} encrypted values could be decrypted, \
+                 is a key provider missing?",
+                undecryptable_count
+            ),
+        });
+    }
+    // The keyring password is missing or wrong, the other cookies are kept
+    if undecryptable_v11_count > 0 {
+        return Err(BrowsercookieError::Decryption {
+            browser: *browser,
+            path: PathBuf::from(sqlite_path),
+            reason: format!(
+                "{} v11 values could not be decrypted with the keyring password, \
+                 is a key provider missing?",
+                undecryptable_v11_count
+            ),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn profiles(
+    browser: &Browser,
+    config_root: Option<&Path>,
+) -> Result<Vec<Profile>, BrowsercookieError> {
+    let flavor = get_flavor(browser).ok_or(BrowsercookieError::UnsupportedBrowser(*browser))?;
+    let config_root = match config_root {
+        Some(path) => PathBuf::from(path),
+        // Without a home directory there is nowhere to look
+        None => get_config_home()
+            .ok_or(BrowsercookieError::ProfileMissing {
+                browser: *browser,
+                path: None,
+            })?
+            .join(flavor.config_dir),
+    };
+    if !config_root.exists() {
+        return Err(BrowsercookieError::ProfileMissing {
+            browser: *browser,
+            path: Some(config_root),
+        });
+    }
+    if !flavor.has_profiles {
+        return Ok(vec![Profile {
+            name: String::from(DEFAULT_PROFILE),
+            path: config_root,
+            is_default: true,
+            install_hash: None,
+        }]);
+    }
+    get_profiles(browser, &config_root)
+}
+
+pub(crate) async fn load(
+    cookies: &mut BrowserCookies,
+    browser: &Browser,
+    profiles: &[Profile],
+    key_providers: &[Arc<dyn KeyProvider>],
+    snapshot: bool,
+    errors: &mut Vec<BrowsercookieError>,
+) {
+    // Loads cookies from profiles of a Chromium based browser. v10 values use
+    // the Linux fallback key, v11 values a key derived from the Safe Storage
+    // password of the key providers. Missing profile directories and databases
+    // that can't be read are reported in errors and skipped, a profile without
+    // any cookie database yet has no cookies.
+    let keyring_application = get_flavor(browser)
+        .map(|f| f.keyring_application)
+        .unwrap_or_default();
+    let mut keys = Keys::new(key_providers, keyring_application);
+    for profile in profiles {
+        if !profile.path.is_dir() {
+            errors.push(BrowsercookieError::InvalidProfile {
+                browser: *browser,
+                path: profile.path.clone(),
+                reason: format!("profile {} doesn't exist", profile.name),
+            });
+            continue;
+        }
+        if let Some(sqlite_path) = get_cookies_path(&profile.path) {
+            let mut profile_cookies = BrowserCookies::new();
+            if let Err(e) = load_from_sqlite(
+                browser,
+                &sqlite_path,
+                &mut profile_cookies,
+                &mut keys,
+                snapshot,
+            )
+            .await
+            {
+                errors.push(e);
+            }
+            cookies.add_from_source(profile_cookies, Some(*browser), Some(profile), &sqlite_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyring::{CommandPassword, StaticPassword};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use strum::IntoEnumIterator;
+
+    #[tokio::test]
+    async fn test_sqlite_load() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/google-chrome/Default/Network/Cookies");
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut keys = Keys::new(&[], "chrome");
+        // Without the keyring password the v11 value is reported, not returned
+        let error = load_from_sqlite(&Browser::Chrome, &path, &mut bcj, &mut keys, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, BrowsercookieError::Decryption { .. }));
+        assert!(error.to_string().contains("1 v11 values"));
+        assert!(bcj.get("keyringname").is_none());
+
+        let encrypted_cookie = bcj.get("chromename").unwrap();
+
+        assert_eq!(encrypted_cookie.value(), "chromevalue");
+        assert_eq!(encrypted_cookie.path(), Some("/"));
+        assert_eq!(encrypted_cookie.domain(), Some("chromehost.example"));
+        assert_eq!(encrypted_cookie.secure(), Some(true));
+        assert_eq!(encrypted_cookie.http_only(), Some(true));
+        assert_eq!(
+            encrypted_cookie.creation_time(),
+            OffsetDateTime::from_unix_timestamp(1_695_526_400).ok()
+        );
+
+        let plain_cookie = bcj.get("plainname").unwrap();
+
+        assert_eq!(plain_cookie.value(), "plainvalue");
+        assert_eq!(plain_cookie.path(), Some("/plain"));
+        assert_eq!(plain_cookie.domain(), Some("plainhost.example"));
+
+        assert!(bcj.get("keyringname").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_load_partitioned() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/Partitioned/Cookies");
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut keys = Keys::new(&[], "chrome");
+        load_from_sqlite(&Browser::Chrome, &path, &mut bcj, &mut keys, false)
+            .await
+            .unwrap();
+
+        assert_eq!(bcj.get_all("embed").count(), 3);
+        let cookie = bcj
+            .get_all("embed")
+            .find(|c| c.value() == "in-news")
+            .unwrap();
+        assert_eq!(
+            cookie.partition_key(),
+            Some(&PartitionKey {
+                scheme: Some(String::from("https")),
+                site: String::from("news.example"),
+                cross_site_ancestor: Some(true),
+            })
+        );
+        let unpartitioned = bcj
+            .get_all("embed")
+            .find(|c| c.value() == "unpartitioned")
+            .unwrap();
+        assert_eq!(unpartitioned.partition_key(), None);
+
+        let widget = url::Url::parse("https://widget.example/").unwrap();
+        let news = url::Url::parse("https://news.example/").unwrap();
+        assert_eq!(bcj.header_for_url(&widget), "embed=unpartitioned");
+        assert_eq!(
+            bcj.header_for_url_under(&widget, &news),
+            "embed=in-news; embed=unpartitioned"
+        );
+        assert_eq!(bcj.header_for_url(&news), "firstparty=partitioned");
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_load_with_key_provider() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/google-chrome/Default/Network/Cookies");
+        let mut bcj = Box::new(BrowserCookies::new());
+        // Stands in for `secret-tool lookup application chrome`
+        let providers: Vec<Arc<dyn KeyProvider>> =
+            vec![Arc::new(CommandPassword::new("echo", &["testpassword"]))];
+        let mut keys = Keys::new(&providers, "chrome");
+        load_from_sqlite(&Browser::Chrome, &path, &mut bcj, &mut keys, false)
+            .await
+            .unwrap();
+
+        let cookie = bcj.get("keyringname").unwrap();
+
+        assert_eq!(cookie.value(), "keyringvalue");
+        assert_eq!(cookie.domain(), Some("keyringhost.example"));
+        assert_eq!(bcj.get("chromename").unwrap().value(), "chromevalue");
+    }
+
+    struct CountingPassword(AtomicUsize);
+
+    impl KeyProvider for CountingPassword {
+        fn password(&self, _application: &str) -> Option<String> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Some(String::from("testpassword"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_providers_asked_on_first_v11_value() {
+        let provider = Arc::new(CountingPassword(AtomicUsize::new(0)));
+        let providers: Vec<Arc<dyn KeyProvider>> = vec![provider.clone()];
+        let mut keys = Keys::new(&providers, "chrome");
+
+        // Brave's cookies only have v10 values
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/BraveSoftware/Brave-Browser/Default/Cookies");
+        let mut bcj = Box::new(BrowserCookies::new());
+        load_from_sqlite(&Browser::Brave, &path, &mut bcj, &mut keys, false)
+            .await
+            .unwrap();
+        assert_eq!(provider.0.load(Ordering::Relaxed), 0);
+
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/google-chrome/Default/Network/Cookies");
+        for _ in 0..2 {
+            let mut bcj = Box::new(BrowserCookies::new());
+            load_from_sqlite(&Browser::Chrome, &path, &mut bcj, &mut keys, false)
+                .await
+                .unwrap();
+            assert_eq!(bcj.get("keyringname").unwrap().value(), "keyringvalue");
+        }
+        assert_eq!(provider.0.load(Ordering::Relaxed), 1);
+    }
+
+    struct PanickingPassword;
+
+    impl KeyProvider for PanickingPassword {
+        fn password(&self, _application: &str) -> Option<String> {
+            panic!("keyring unavailable");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panicking_key_provider() {
+        let providers: Vec<Arc<dyn KeyProvider>> = vec![
+            Arc::new(PanickingPassword),
+            Arc::new(StaticPassword::new("testpassword")),
+        ];
+        let mut keys = Keys::new(&providers, "chrome");
+        keys.resolve_v11().await;
+        assert_eq!(
+            keys.v11,
+            Some(vec![derive_key(b"testpassword"), derive_key(b"")])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_flavor() {
+        let mut bcj = Box::new(BrowserCookies::new());
+        let profiles = profiles(&Browser::Brave, None).unwrap();
+        let mut errors = vec![];
+        load(
+            &mut bcj,
+            &Browser::Brave,
+            &profiles,
+            &[],
+            false,
+            &mut errors,
+        )
+        .await;
+
+        assert!(errors.is_empty());
+
+        assert_eq!(bcj.iter().count(), 2);
+        let cookie = bcj.get("bravename").unwrap();
+
+        assert_eq!(cookie.value(), "bravevalue");
+        assert_eq!(cookie.domain(), Some("bravehost.example"));
+    }
+
+    #[tokio::test]
+    async fn test_load_missing_profile_directory() {
+        let mut bcj = Box::new(BrowserCookies::new());
+        let profiles = vec![Profile {
+            name: String::from("Gone"),
+            path: PathBuf::from("/nonexistent/google-chrome/Profile 9"),
+            is_default: true,
+            install_hash: None,
+        }];
+        let mut errors = vec![];
+        load(
+            &mut bcj,
+            &Browser::Chrome,
+            &profiles,
+            &[],
+            false,
+            &mut errors,
+        )
+        .await;
+
+        assert!(bcj.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            BrowsercookieError::InvalidProfile { .. }
+        ));
+        assert!(errors[0].to_string().contains("profile Gone doesn't exist"));
+    }
+
+    #[test]
+    fn test_flavors() {
+        let flavors: Vec<(Browser, &str, &str, bool)> = Browser::iter()
+            .filter_map(|browser| {
+                let flavor = get_flavor(&browser)?;
+                Some((
+                    browser,
+                    flavor.config_dir,
+                    flavor.keyring_application,
+                    flavor.has_profiles,
+                ))
+            })
+            .collect();
+        assert_eq!(
+            flavors,
+            [
+                (Browser::Chrome, "google-chrome", "chrome", true),
+                (Browser::ChromeBeta, "google-chrome-beta", "chrome", true),
+                (Browser::ChromeDev, "google-chrome-unstable", "chrome", true),
+                (
+                    Browser::ChromeCanary,
+                    "google-chrome-canary",
+                    "chrome",
+                    true
+                ),
+                (Browser::Chromium, "chromium", "chromium", true),
+                (Browser::Brave, "BraveSoftware/Brave-Browser", "brave", true),
+                (Browser::Edge, "microsoft-edge", "chromium", true),
+                (Browser::Vivaldi, "vivaldi", "chrome", true),
+                (Browser::Opera, "opera", "chromium", false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_opera_without_profiles() {
+        let config_root = tempfile::tempdir().unwrap();
+        let mut cookies_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        cookies_path.push("tests/resources/BraveSoftware/Brave-Browser/Default/Cookies");
+        fs::copy(&cookies_path, config_root.path().join("Cookies")).unwrap();
+
+        let profiles = profiles(&Browser::Opera, Some(config_root.path())).unwrap();
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].path, config_root.path());
+        assert!(profiles[0].is_default);
+
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut errors = vec![];
+        load(
+            &mut bcj,
+            &Browser::Opera,
+            &profiles,
+            &[],
+            false,
+            &mut errors,
+        )
+        .await;
+        assert!(errors.is_empty());
+        assert_eq!(bcj.get("bravename").unwrap().value(), "bravevalue");
+    }
+
+    #[test]
+    fn test_missing_flavor() {
+        assert!(matches!(
+            profiles(&Browser::Vivaldi, None),
+            Err(BrowsercookieError::ProfileMissing { .. })
+        ));
+        assert!(matches!(
+            profiles(&Browser::Firefox, None),
+            Err(BrowsercookieError::UnsupportedBrowser(Browser::Firefox))
+        ));
+    }
+
+    #[test]
+    fn test_get_profiles() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/google-chrome");
+
+        let profiles = get_profiles(&Browser::Chrome, &path).unwrap();
+
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].name, "Person 1");
+        assert!(profiles[0].path.ends_with("google-chrome/Default"));
+        assert!(profiles[0].is_default);
+        assert_eq!(profiles[1].name, "Work");
+        assert!(profiles[1].path.ends_with("google-chrome/Profile 1"));
+        assert!(!profiles[1].is_default);
+    }
+
+    #[test]
+    fn test_get_profiles_without_local_state() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/BraveSoftware/Brave-Browser");
+
+        let profiles = get_profiles(&Browser::Brave, &path).unwrap();
+
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].name, "Default");
+        assert!(profiles[0].is_default);
+    }
+
+    #[test]
+    fn test_decrypt_value_without_host_digest() {
+        // "v10" + AES-128-CBC("somevalue") with the peanuts key
+        let encrypted = [
+            b"v10".as_slice(),
+            &[
+                0x5a, 0x50, 0x92, 0x88, 0x1b, 0x8c, 0xdf, 0x7d, 0xc4, 0xc7, 0x8d, 0x8b, 0x31, 0x14,
+                0xc6, 0x5b,
+            ],
+        ]
+        .concat();
+        let keys = Keys::new(&[], "chrome");
+
+        assert_eq!(
+            decrypt_value(&encrypted, &keys, "somehost", false).unwrap(),
+            "somevalue"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_load_without_any_key() {
+        // With a wrong fallback key neither the v10 nor the v11 value decrypts
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/google-chrome/Default/Network/Cookies");
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut keys = Keys::new(&[], "chrome");
+        keys.v10 = derive_key(b"wrongpassword");
+
+        let error = load_from_sqlite(&Browser::Chrome, &path, &mut bcj, &mut keys, false)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, BrowsercookieError::Decryption { .. }));
+        assert_eq!(error.path(), Some(path.as_path()));
+        assert_eq!(bcj.get("plainname").unwrap().value(), "plainvalue");
+    }
+}
@@ -0,0 +1,839 @@
+//! Cookies found in browsers, with what the browser knows about them
+use cookie::time::OffsetDateTime;
+use cookie::{Cookie, CookieJar};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use strum_macros::{Display, EnumString};
+use url::Url;
+
+use crate::{Browser, Profile};
+
+/// A cookie read from a browser
+///
+/// Derefs to the [`Cookie`] itself, so its attributes are available directly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrowserCookie {
+    pub(crate) cookie: Cookie<'static>,
+    pub(crate) host_only: bool,
+    pub(crate) creation_time: Option<OffsetDateTime>,
+    pub(crate) last_accessed: Option<OffsetDateTime>,
+    pub(crate) browser: Option<Browser>,
+    pub(crate) profile: Option<String>,
+    pub(crate) source_file: Option<PathBuf>,
+    pub(crate) container: Option<String>,
+    pub(crate) partition_key: Option<PartitionKey>,
+}
+
+/// The top-level site a partitioned cookie is kept for
+///
+/// Chromium partitions CHIPS cookies, Firefox partitions third-party cookies
+/// with Total Cookie Protection and every cookie with first-party isolation
+/// (as in Tor Browser). Such cookies are only sent under their top-level site.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PartitionKey {
+    /// Scheme of the top-level site, none for first-party isolation
+    pub scheme: Option<String>,
+    /// Registrable domain of the top-level site, e.g. "example.com"
+    pub site: String,
+    /// Whether a frame between the top-level page and the cookie's was
+    /// cross-site, none when the browser doesn't record it
+    pub cross_site_ancestor: Option<bool>,
+}
+
+impl PartitionKey {
+    fn matches(&self, host: &str, top_level: &Url) -> bool {
+        let top_level_host = match top_level.host_str() {
+            Some(host) => host,
+            None => return false,
+        };
+        if self
+            .scheme
+            .as_ref()
+            .is_some_and(|scheme| scheme != top_level.scheme())
+        {
+            return false;
+        }
+        if !domain_match(top_level_host, &self.site, false) {
+            return false;
+        }
+        // Requests to another site than the top-level one have a cross-site ancestor
+        self.cross_site_ancestor
+            .is_none_or(|cross_site| cross_site != domain_match(host, &self.site, false))
+    }
+}
+
+impl fmt::Display for PartitionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.scheme {
+            Some(scheme) => write!(f, "{}://{}", scheme, self.site),
+            None => write!(f, "{}", self.site),
+        }
+    }
+}
+
+impl BrowserCookie {
+    pub fn new(cookie: Cookie<'static>) -> Self {
+        BrowserCookie {
+            cookie,
+            host_only: false,
+            creation_time: None,
+            last_accessed: None,
+            browser: None,
+            profile: None,
+            source_file: None,
+            container: None,
+            partition_key: None,
+        }
+    }
+
+    pub fn cookie(&self) -> &Cookie<'static> {
+        &self.cookie
+    }
+
+    pub fn into_cookie(self) -> Cookie<'static> {
+        self.cookie
+    }
+
+    /// Whether the cookie was set without a Domain attribute, so it is only
+    /// sent to the exact host that set it
+    pub fn is_host_only(&self) -> bool {
+        self.host_only
+    }
+
+    /// When the browser first stored the cookie
+    pub fn creation_time(&self) -> Option<OffsetDateTime> {
+        self.creation_time
+    }
+
+    /// When the browser last sent the cookie, for Firefox session cookies when
+    /// their session file was saved
+    pub fn last_accessed(&self) -> Option<OffsetDateTime> {
+        self.last_accessed
+    }
+
+    /// The browser the cookie was read from, none for cookie files
+    pub fn browser(&self) -> Option<Browser> {
+        self.browser
+    }
+
+    /// Name of the browser profile the cookie was read from
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// The file the cookie was read from
+    pub fn source_file(&self) -> Option<&Path> {
+        self.source_file.as_deref()
+    }
+
+    /// Name of the Firefox container the cookie belongs to, none outside of
+    /// containers. Containers missing from `containers.json` are named by their id.
+    pub fn container(&self) -> Option<&str> {
+        self.container.as_deref()
+    }
+
+    /// The top-level site the cookie is partitioned under, none for cookies
+    /// sent under any top-level site
+    pub fn partition_key(&self) -> Option<&PartitionKey> {
+        self.partition_key.as_ref()
+    }
+
+    /// Whether a browser would send this cookie along with a request to `url`
+    /// made by a page of that same site
+    ///
+    /// Applies the domain-match and path-match rules of RFC 6265, sends Secure
+    /// cookies only over https and leaves out expired cookies. Partitioned
+    /// cookies are only sent when `url` is on their top-level site.
+    pub fn matches_url(&self, url: &Url) -> bool {
+        self.matches_url_at(url, url, OffsetDateTime::now_utc())
+    }
+
+    /// Whether a browser would send this cookie along with a request to `url`
+    /// made by a frame of the page at `top_level`
+    pub fn matches_url_under(&self, url: &Url, top_level: &Url) -> bool {
+        self.matches_url_at(url, top_level, OffsetDateTime::now_utc())
+    }
+
+    fn matches_url_at(&self, url: &Url, top_level: &Url, now: OffsetDateTime) -> bool {
+        let (host, domain) = match (url.host_str(), self.domain()) {
+            (Some(host), Some(domain)) => (host, domain),
+            _ => return false,
+        };
+        if !domain_match(host, domain, self.host_only) {
+            return false;
+        }
+        if !path_match(url.path(), self.path().unwrap_or("/")) {
+            return false;
+        }
+        if self.secure() == Some(true) && !matches!(url.scheme(), "https" | "wss") {
+            return false;
+        }
+        if let Some(partition_key) = &self.partition_key {
+            if !partition_key.matches(host, top_level) {
+                return false;
+            }
+        }
+        self.expires_datetime().is_none_or(|expires| expires > now)
+    }
+
+    #[cfg(feature = "reqwest")]
+    pub(crate) fn is_same(&self, other: &BrowserCookie) -> bool {
+        self.key() == other.key()
+    }
+
+    fn key(&self) -> CookieKey {
+        CookieKey {
+            name: String::from(self.name()),
+            domain: self.domain().map(String::from),
+            host_only: self.host_only,
+            path: self.path().map(String::from),
+            container: self.container.clone(),
+            partition_key: self.partition_key.clone(),
+            profile: None,
+        }
+    }
+}
+
+// Domain, path, name, container and partition key, which tell cookies apart,
+// and the profile for browsers whose profiles are kept apart
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CookieKey {
+    name: String,
+    domain: Option<String>,
+    host_only: bool,
+    path: Option<String>,
+    container: Option<String>,
+    partition_key: Option<PartitionKey>,
+    profile: Option<(Browser, Option<String>)>,
+}
+
+pub(crate) fn domain_match(host: &str, domain: &str, host_only: bool) -> bool {
+    // RFC 6265 5.1.3, host-only cookies only match their own host (5.4)
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.eq_ignore_ascii_case(domain) {
+        return true;
+    }
+    if host_only || host.parse::<IpAddr>().is_ok() {
+        return false;
+    }
+    host.len() > domain.len()
+        && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+        && host[host.len() - domain.len()..].eq_ignore_ascii_case(domain)
+}
+
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    // RFC 6265 5.1.4
+    let request_path = if request_path.starts_with('/') {
+        request_path
+    } else {
+        "/"
+    };
+    match request_path.strip_prefix(cookie_path) {
+        Some(rest) => rest.is_empty() || cookie_path.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+// Longest path first, then the earliest created first (RFC 6265 5.4)
+fn send_order(cookie: &BrowserCookie) -> (Reverse<usize>, bool, Option<OffsetDateTime>) {
+    (
+        Reverse(cookie.path().unwrap_or("/").len()),
+        cookie.creation_time.is_none(),
+        cookie.creation_time,
+    )
+}
+
+fn join_header<'a>(cookies: impl Iterator<Item = &'a BrowserCookie>) -> String {
+    cookies
+        .map(|c| format!("{}={}", c.name(), c.value()))
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+impl Deref for BrowserCookie {
+    type Target = Cookie<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cookie
+    }
+}
+
+impl fmt::Display for BrowserCookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.cookie.fmt(f)
+    }
+}
+
+impl From<Cookie<'static>> for BrowserCookie {
+    fn from(cookie: Cookie<'static>) -> Self {
+        BrowserCookie::new(cookie)
+    }
+}
+
+/// Which cookie is kept when several sources have the same one
+///
+/// Cookies are the same when their domain, path, name, Firefox container and
+/// partition key are, e.g. a Firefox session cookie also in `cookies.sqlite`,
+/// or a cookie of several browsers or cookie files. When several profiles of a
+/// browser are read, usually different accounts, each profile keeps its own
+/// cookies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum MergePolicy {
+    /// Keeps the cookie last accessed or created most recently. Firefox session
+    /// cookies count as accessed when their session file was saved, cookies
+    /// without any time, like those of cookie files, lose against the others.
+    #[default]
+    NewestWins,
+    /// Keeps the cookie with an expiry date over a session cookie, then the newest
+    PreferPersistent,
+    /// Keeps the session cookie over one with an expiry date, then the newest
+    PreferSession,
+    /// Keeps every cookie, same ones included
+    KeepAll,
+}
+
+impl MergePolicy {
+    // Whether `new` replaces `existing`, which was read from an earlier source
+    fn prefers(&self, new: &BrowserCookie, existing: &BrowserCookie) -> bool {
+        // Ties go to the cookie read last
+        let recency = |c: &BrowserCookie| c.last_accessed.max(c.creation_time);
+        let is_newer = recency(new) >= recency(existing);
+        let is_persistent = |c: &BrowserCookie| c.expires_datetime().is_some();
+        match (self, is_persistent(new), is_persistent(existing)) {
+            (MergePolicy::PreferPersistent, true, false) => true,
+            (MergePolicy::PreferPersistent, false, true) => false,
+            (MergePolicy::PreferSession, false, true) => true,
+            (MergePolicy::PreferSession, true, false) => false,
+            _ => is_newer,
+        }
+    }
+}
+
+/// Cookies found in browsers
+///
+/// Unlike a [`CookieJar`], which keeps a single cookie per name, this keeps
+/// one cookie per (domain, path, name, container, partition key), so same
+/// named cookies of different sites, Firefox containers or partitions don't
+/// replace each other. Cookies found with [`MergePolicy::KeepAll`] may hold
+/// several of the same cookie.
+#[derive(Debug, Clone, Default)]
+pub struct BrowserCookies {
+    cookies: Vec<BrowserCookie>,
+    // Position in cookies of the first cookie with each key, so adding a
+    // cookie doesn't scan all of them
+    index: HashMap<CookieKey, usize>,
+    // How add_from_source merges cookies read from different files
+    pub(crate) merge_policy: MergePolicy,
+    // Browsers read with several profiles, usually different accounts, whose
+    // cookies are only merged within their own profile
+    pub(crate) separate_profiles: BTreeSet<Browser>,
+}
+
+impl BrowserCookies {
+    pub fn new() -> Self {
+        BrowserCookies::default()
+    }
+
+    /// Adds a cookie, replacing the one with the same domain, path, name,
+    /// container and partition key
+    pub fn add(&mut self, cookie: impl Into<BrowserCookie>) {
+        let cookie = cookie.into();
+        match self.index.get(&self.key(&cookie)) {
+            Some(&i) => self.cookies[i] = cookie,
+            None => self.push(cookie),
+        }
+    }
+
+    fn key(&self, cookie: &BrowserCookie) -> CookieKey {
+        let mut key = cookie.key();
+        if let Some(browser) = cookie.browser {
+            if self.separate_profiles.contains(&browser) {
+                key.profile = Some((browser, cookie.profile.clone()));
+            }
+        }
+        key
+    }
+
+    fn push(&mut self, cookie: BrowserCookie) {
+        let key = self.key(&cookie);
+        self.index.entry(key).or_insert(self.cookies.len());
+        self.cookies.push(cookie);
+    }
+
+    // Called after cookies were removed or reordered
+    fn reindex(&mut self) {
+        let keys: Vec<CookieKey> = self.cookies.iter().map(|c| self.key(c)).collect();
+        self.index.clear();
+        for (i, key) in keys.into_iter().enumerate() {
+            self.index.entry(key).or_insert(i);
+        }
+    }
+
+    // Adds cookies read from one file, recording where they came from
+    pub(crate) fn add_from_source(
+        &mut self,
+        cookies: BrowserCookies,
+        browser: Option<Browser>,
+        profile: Option<&Profile>,
+        source_file: &Path,
+    ) {
+        for mut cookie in cookies {
+            cookie.browser = browser;
+            cookie.profile = profile.map(|p| p.name.clone());
+            cookie.source_file = Some(PathBuf::from(source_file));
+            if self.merge_policy == MergePolicy::KeepAll {
+                self.push(cookie);
+                continue;
+            }
+            match self.index.get(&self.key(&cookie)) {
+                Some(&i) => {
+                    if self.merge_policy.prefers(&cookie, &self.cookies[i]) {
+                        self.cookies[i] = cookie;
+                    }
+                }
+                None => self.push(cookie),
+            }
+        }
+    }
+
+    /// Returns the first cookie with this name
+    pub fn get(&self, name: &str) -> Option<&BrowserCookie> {
+        self.cookies.iter().find(|c| c.name() == name)
+    }
+
+    /// Returns every cookie with this name
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a BrowserCookie> {
+        self.cookies.iter().filter(move |c| c.name() == name)
+    }
+
+    /// Returns the cookies set for exactly this domain, with or without a leading dot
+    pub fn get_for_domain<'a>(
+        &'a self,
+        domain: &'a str,
+    ) -> impl Iterator<Item = &'a BrowserCookie> {
+        let domain = domain.trim_start_matches('.');
+        self.cookies
+            .iter()
+            .filter(move |c| c.domain() == Some(domain))
+    }
+
+    /// Keeps only the cookies for which `f` returns true
+    pub fn retain(&mut self, f: impl FnMut(&BrowserCookie) -> bool) {
+        self.cookies.retain(f);
+        self.reindex();
+    }
+
+    /// Keeps the cookies a browser would send to `url`, in the order it would
+    /// send them: longest path first, then the earliest created first
+    pub fn retain_for_url(&mut self, url: &Url) {
+        self.retain_for_url_at(url, url, OffsetDateTime::now_utc());
+    }
+
+    /// Same as [`retain_for_url`](Self::retain_for_url), for a request made by
+    /// a frame of the page at `top_level`
+    pub fn retain_for_url_under(&mut self, url: &Url, top_level: &Url) {
+        self.retain_for_url_at(url, top_level, OffsetDateTime::now_utc());
+    }
+
+    fn retain_for_url_at(&mut self, url: &Url, top_level: &Url, now: OffsetDateTime) {
+        self.cookies
+            .retain(|c| c.matches_url_at(url, top_level, now));
+        self.cookies.sort_by_key(send_order);
+        self.reindex();
+    }
+
+    /// Returns the value of the `Cookie` header a browser would send to `url`
+    ///
+    /// Same as [`retain_for_url`](Self::retain_for_url) followed by
+    /// [`to_header`](Self::to_header), without dropping any cookie.
+    pub fn header_for_url(&self, url: &Url) -> String {
+        self.header_for_url_under(url, url)
+    }
+
+    /// Same as [`header_for_url`](Self::header_for_url), for a request made by
+    /// a frame of the page at `top_level`
+    pub fn header_for_url_under(&self, url: &Url, top_level: &Url) -> String {
+        let now = OffsetDateTime::now_utc();
+        let mut cookies: Vec<&BrowserCookie> = self
+            .cookies
+            .iter()
+            .filter(|c| c.matches_url_at(url, top_level, now))
+            .collect();
+        cookies.sort_by_key(|c| send_order(c));
+        join_header(cookies.into_iter())
+    }
+
+    /// Joins the cookies into the value of a `Cookie` request header, `a=1; b=2`
+    ///
+    /// Cookies are joined in their current order, so narrow them down to one
+    /// request with [`retain_for_url`](Self::retain_for_url) first.
+    pub fn to_header(&self) -> String {
+        join_header(self.cookies.iter())
+    }
+
+    /// Same as [`to_header`](Self::to_header), typed for the `http` crate
+    #[cfg(feature = "http")]
+    pub fn to_header_value(&self) -> Result<http::HeaderValue, http::header::InvalidHeaderValue> {
+        http::HeaderValue::from_str(&self.to_header())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BrowserCookie> {
+        self.cookies.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+impl IntoIterator for BrowserCookies {
+    type Item = BrowserCookie;
+    type IntoIter = std::vec::IntoIter<BrowserCookie>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.cookies.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a BrowserCookies {
+    type Item = &'a BrowserCookie;
+    type IntoIter = std::slice::Iter<'a, BrowserCookie>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.cookies.iter()
+    }
+}
+
+impl From<BrowserCookies> for CookieJar {
+    /// Same named cookies collapse into the one added last
+    fn from(cookies: BrowserCookies) -> Self {
+        let mut cookie_jar = CookieJar::new();
+        for cookie in cookies {
+            cookie_jar.add(cookie.into_cookie());
+        }
+        cookie_jar
+    }
+}
+
+#[cfg(feature = "cookie_store")]
+impl From<BrowserCookies> for cookie_store::CookieStore {
+    /// Host-only cookies stay host-only. Expired cookies, and those whose
+    /// domain isn't a valid host, are left out.
+    fn from(cookies: BrowserCookies) -> Self {
+        let mut store = cookie_store::CookieStore::default();
+        for browser_cookie in cookies {
+            let host_only = browser_cookie.host_only;
+            let mut cookie = browser_cookie.into_cookie();
+            // Stored as if the site itself had just set the cookie
+            let url = match cookie
+                .domain()
+                .map(|domain| format!("https://{}{}", domain, cookie.path().unwrap_or("/")))
+                .and_then(|url| Url::parse(&url).ok())
+            {
+                Some(url) => url,
+                None => continue,
+            };
+            if host_only {
+                cookie.unset_domain();
+            }
+            let _ = store.insert_raw(&cookie, &url);
+        }
+        store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookie(name: &str, value: &str, domain: &str) -> Cookie<'static> {
+        Cookie::build((String::from(name), String::from(value)))
+            .domain(String::from(domain))
+            .path("/")
+            .build()
+    }
+
+    #[test]
+    fn test_same_name_different_domains() {
+        let mut cookies = BrowserCookies::new();
+        cookies.add(cookie("session", "github", "github.com"));
+        cookies.add(cookie("session", "gitlab", "gitlab.com"));
+
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies.get_all("session").count(), 2);
+        let github = cookies.get_for_domain(".github.com").next().unwrap();
+        assert_eq!(github.value(), "github");
+        let gitlab = cookies.get_for_domain("gitlab.com").next().unwrap();
+        assert_eq!(gitlab.value(), "gitlab");
+    }
+
+    #[test]
+    fn test_merge_policies() {
+        let now = OffsetDateTime::now_utc();
+        // As read from a session file saved just now
+        let mut session = BrowserCookie::new(cookie("sid", "session", "example.com"));
+        session.last_accessed = Some(now);
+        let mut persistent = BrowserCookie::new(cookie("sid", "persistent", "example.com"));
+        persistent
+            .cookie
+            .set_expires(now + cookie::time::Duration::days(1));
+        persistent.last_accessed = Some(now - cookie::time::Duration::hours(1));
+
+        let merged = |merge_policy| {
+            let mut cookies = BrowserCookies::new();
+            cookies.merge_policy = merge_policy;
+            for (source, c) in [
+                ("recovery.jsonlz4", &session),
+                ("cookies.sqlite", &persistent),
+            ] {
+                let mut source_cookies = BrowserCookies::new();
+                source_cookies.add(c.clone());
+                cookies.add_from_source(
+                    source_cookies,
+                    Some(Browser::Firefox),
+                    None,
+                    Path::new(source),
+                );
+            }
+            cookies
+                .iter()
+                .map(|c| String::from(c.value()))
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(merged(MergePolicy::NewestWins), ["session"]);
+        assert_eq!(merged(MergePolicy::PreferPersistent), ["persistent"]);
+        assert_eq!(merged(MergePolicy::PreferSession), ["session"]);
+        assert_eq!(merged(MergePolicy::KeepAll), ["session", "persistent"]);
+        assert_eq!(
+            "prefer-session".parse::<MergePolicy>(),
+            Ok(MergePolicy::PreferSession)
+        );
+    }
+
+    #[test]
+    fn test_replaces_after_retain_and_reorder() {
+        let mut cookies = BrowserCookies::new();
+        cookies.add(cookie("dropped", "1", "github.com"));
+        cookies.add(cookie("session", "old", "github.com"));
+        cookies.add(cookie("other", "1", "gitlab.com"));
+        cookies.retain(|c| c.name() != "dropped");
+        cookies.add(cookie("session", "new", "github.com"));
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies.get("session").unwrap().value(), "new");
+
+        cookies.retain_for_url(&url("https://github.com/"));
+        cookies.add(cookie("session", "newer", "github.com"));
+        cookies.add(cookie("other", "2", "gitlab.com"));
+        let values: Vec<&str> = cookies.iter().map(|c| c.value()).collect();
+        assert_eq!(values, ["newer", "2"]);
+    }
+
+    #[test]
+    fn test_same_domain_path_and_name_replaces() {
+        let mut cookies = BrowserCookies::new();
+        cookies.add(cookie("session", "old", "github.com"));
+        cookies.add(cookie("session", "new", "github.com"));
+
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies.get("session").unwrap().value(), "new");
+    }
+
+    #[test]
+    fn test_into_cookie_jar() {
+        let mut cookies = BrowserCookies::new();
+        cookies.add(cookie("session", "github", "github.com"));
+        cookies.add(cookie("session", "gitlab", "gitlab.com"));
+        cookies.add(cookie("other", "value", "github.com"));
+
+        let cookie_jar = CookieJar::from(cookies);
+
+        assert_eq!(cookie_jar.iter().count(), 2);
+        assert_eq!(cookie_jar.get("session").unwrap().value(), "gitlab");
+    }
+
+    #[test]
+    fn test_to_header() {
+        let mut cookies = BrowserCookies::new();
+        assert_eq!(cookies.to_header(), "");
+
+        cookies.add(cookie("session", "abc", "example.com"));
+        cookies.add(cookie("theme", "dark", "example.com"));
+
+        assert_eq!(cookies.to_header(), "session=abc; theme=dark");
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_to_header_value() {
+        let mut cookies = BrowserCookies::new();
+        cookies.add(cookie("session", "abc", "example.com"));
+
+        assert_eq!(cookies.to_header_value().unwrap(), "session=abc");
+    }
+
+    #[cfg(feature = "cookie_store")]
+    #[test]
+    fn test_into_cookie_store() {
+        let mut cookies = BrowserCookies::new();
+        cookies.add(browser_cookie(".example.com", "/", false));
+        let mut host_only = browser_cookie("example.com", "/docs", true);
+        host_only.cookie.set_name("host_only");
+        host_only.cookie.set_secure(true);
+        host_only.cookie.set_http_only(true);
+        cookies.add(host_only);
+        let mut expired = browser_cookie("example.com", "/", true);
+        expired.cookie.set_name("expired");
+        expired
+            .cookie
+            .set_expires(OffsetDateTime::now_utc() - cookie::time::Duration::hours(1));
+        cookies.add(expired);
+
+        let store = cookie_store::CookieStore::from(cookies);
+
+        let names = |url: &str| {
+            let mut names: Vec<String> = store
+                .matches(&Url::parse(url).unwrap())
+                .iter()
+                .map(|c| String::from(c.name()))
+                .collect();
+            names.sort_unstable();
+            names
+        };
+        assert_eq!(names("https://example.com/docs"), ["host_only", "name"]);
+        assert_eq!(names("http://example.com/docs"), ["name"]);
+        assert_eq!(names("https://api.example.com/docs"), ["name"]);
+        let host_only = store.get("example.com", "/docs", "host_only").unwrap();
+        assert_eq!(host_only.http_only(), Some(true));
+    }
+
+    fn browser_cookie(domain: &str, path: &str, host_only: bool) -> BrowserCookie {
+        let mut cookie = BrowserCookie::new(
+            Cookie::build((String::from("name"), String::from("value")))
+                .domain(String::from(domain))
+                .path(String::from(path))
+                .build(),
+        );
+        cookie.host_only = host_only;
+        cookie
+    }
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn test_domain_match() {
+        let domain_cookie = browser_cookie(".example.com", "/", false);
+        assert!(domain_cookie.matches_url(&url("https://example.com/")));
+        assert!(domain_cookie.matches_url(&url("https://api.EXAMPLE.com/")));
+        assert!(!domain_cookie.matches_url(&url("https://notexample.com/")));
+        assert!(!domain_cookie.matches_url(&url("https://example.com.evil/")));
+
+        let host_only_cookie = browser_cookie("example.com", "/", true);
+        assert!(host_only_cookie.matches_url(&url("https://example.com/")));
+        assert!(!host_only_cookie.matches_url(&url("https://api.example.com/")));
+
+        let ip_cookie = browser_cookie("0.0.1", "/", false);
+        assert!(!ip_cookie.matches_url(&url("http://127.0.0.1/")));
+    }
+
+    #[test]
+    fn test_path_match() {
+        let cookie = browser_cookie("example.com", "/docs", true);
+        assert!(cookie.matches_url(&url("https://example.com/docs")));
+        assert!(cookie.matches_url(&url("https://example.com/docs/page")));
+        assert!(!cookie.matches_url(&url("https://example.com/docsearch")));
+        assert!(!cookie.matches_url(&url("https://example.com/")));
+
+        let cookie = browser_cookie("example.com", "/docs/", true);
+        assert!(cookie.matches_url(&url("https://example.com/docs/page")));
+        assert!(!cookie.matches_url(&url("https://example.com/docs")));
+    }
+
+    #[test]
+    fn test_secure_and_expiry() {
+        let mut cookie = browser_cookie("example.com", "/", true);
+        cookie.cookie.set_secure(true);
+        assert!(cookie.matches_url(&url("https://example.com/")));
+        assert!(!cookie.matches_url(&url("http://example.com/")));
+
+        let now = OffsetDateTime::now_utc();
+        let request = url("https://example.com/");
+        cookie
+            .cookie
+            .set_expires(now - cookie::time::Duration::hours(1));
+        assert!(!cookie.matches_url_at(&request, &request, now));
+        cookie
+            .cookie
+            .set_expires(now + cookie::time::Duration::hours(1));
+        assert!(cookie.matches_url_at(&request, &request, now));
+    }
+
+    #[test]
+    fn test_partition_key() {
+        let widget = url("https://widget.example/embed");
+        let mut cookie = browser_cookie(".widget.example", "/", false);
+        cookie.partition_key = Some(PartitionKey {
+            scheme: Some(String::from("https")),
+            site: String::from("news.example"),
+            cross_site_ancestor: Some(true),
+        });
+        assert_eq!(
+            cookie.partition_key().unwrap().to_string(),
+            "https://news.example"
+        );
+
+        assert!(cookie.matches_url_under(&widget, &url("https://www.news.example/")));
+        assert!(!cookie.matches_url_under(&widget, &url("http://news.example/")));
+        assert!(!cookie.matches_url_under(&widget, &url("https://shop.example/")));
+        // Visiting the widget's own site isn't under news.example
+        assert!(!cookie.matches_url(&widget));
+
+        // Partitioned cookies set by the top-level site itself
+        cookie.partition_key = Some(PartitionKey {
+            scheme: Some(String::from("https")),
+            site: String::from("widget.example"),
+            cross_site_ancestor: Some(false),
+        });
+        assert!(cookie.matches_url(&widget));
+        assert!(!cookie.matches_url_under(&widget, &url("https://news.example/")));
+
+        // First-party isolation doesn't record the scheme nor the ancestors
+        cookie.partition_key = Some(PartitionKey {
+            scheme: None,
+            site: String::from("news.example"),
+            cross_site_ancestor: None,
+        });
+        assert!(cookie.matches_url_under(&widget, &url("http://news.example/")));
+        assert!(!cookie.matches_url(&widget));
+    }
+
+    #[test]
+    fn test_retain_for_url_order() {
+        let now = OffsetDateTime::now_utc();
+        let mut cookies = BrowserCookies::new();
+        for (name, path, age) in [("old", "/", 2), ("new", "/", 1), ("deep", "/a/b", 0)] {
+            let mut cookie = browser_cookie("example.com", path, true);
+            cookie.cookie.set_name(name);
+            cookie.creation_time = Some(now - cookie::time::Duration::hours(age));
+            cookies.add(cookie);
+        }
+        cookies.add(browser_cookie("other.com", "/", true));
+
+        let request = url("https://example.com/a/b/c");
+        cookies.retain_for_url_at(&request, &request, now);
+
+        let names: Vec<&str> = cookies.iter().map(|c| c.name()).collect();
+        assert_eq!(names, ["deep", "old", "new"]);
+    }
+}
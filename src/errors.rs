@@ -1,29 +1,304 @@
 //! Exported errors if library users wish to handle certain failure cases
 use std::error;
 use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
 
+use crate::Browser;
+
+// SQLITE_BUSY and SQLITE_LOCKED
+const SQLITE_LOCKED_CODES: [i32; 2] = [5, 6];
+
+// SQLite may report extended result codes (e.g. 261 for SQLITE_BUSY_RECOVERY),
+// whose low byte is the primary one
+fn is_locked_code(code: &str) -> bool {
+    code.parse::<i32>()
+        .is_ok_and(|code| SQLITE_LOCKED_CODES.contains(&(code & 0xff)))
+}
+
+/// Failure to read cookies from a browser, one of its profiles or one of its files
+///
+/// Variants tell which browser they are about, if any, and the ones about a
+/// file or directory tell which one, so `Display` alone says what failed and why.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum BrowsercookieError {
-    ProfileMissing(String),
-    InvalidProfile(String),
-    InvalidCookieStore(String),
-    InvalidRecovery(String),
+    /// The browser has no (matching) profile, most likely it isn't installed
+    ProfileMissing {
+        browser: Browser,
+        path: Option<PathBuf>,
+    },
+    /// The profile list can't be parsed, or a profile points to nothing usable
+    InvalidProfile {
+        browser: Browser,
+        path: PathBuf,
+        reason: String,
+    },
+    /// The cookie database can't be opened or queried
+    InvalidCookieStore {
+        browser: Browser,
+        path: PathBuf,
+        source: sqlx::Error,
+    },
+    /// The cookie database is locked by the running browser
+    ///
+    /// Reported when SQLite answers SQLITE_BUSY or SQLITE_LOCKED. Cookie databases
+    /// are opened immutable or read from a private snapshot, neither of which
+    /// waits on the browser's locks, so finding cookies doesn't run into it at
+    /// the moment.
+    DatabaseLocked {
+        browser: Browser,
+        path: PathBuf,
+        source: sqlx::Error,
+    },
+    /// The cookie database has a layout this crate doesn't know about
+    UnsupportedSchema {
+        browser: Browser,
+        path: PathBuf,
+        version: String,
+    },
+    /// A Firefox session file (recovery.jsonlz4, sessionstore.jsonlz4...) isn't
+    /// a mozLz4 archive of cookies
+    InvalidRecovery { path: PathBuf, reason: String },
+    /// Encrypted cookie values can't be decrypted with any known key, either
+    /// all of them or the `v11` ones encrypted with the keyring password
+    Decryption {
+        browser: Browser,
+        path: PathBuf,
+        reason: String,
+    },
+    /// A file can't be read, or its lz4 content can't be decompressed
+    Io {
+        browser: Option<Browser>,
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A JSON file (Local State, session recovery) can't be parsed
+    Json {
+        browser: Browser,
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// The browser isn't supported by the loader it was given to
+    UnsupportedBrowser(Browser),
+    /// A line of a Netscape cookie file can't be parsed
+    InvalidCookieFile {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+}
+
+impl BrowsercookieError {
+    /// The browser the error is about, none for cookie files
+    pub fn browser(&self) -> Option<Browser> {
+        match self {
+            BrowsercookieError::ProfileMissing { browser, .. }
+            | BrowsercookieError::InvalidProfile { browser, .. }
+            | BrowsercookieError::InvalidCookieStore { browser, .. }
+            | BrowsercookieError::DatabaseLocked { browser, .. }
+            | BrowsercookieError::UnsupportedSchema { browser, .. }
+            | BrowsercookieError::Decryption { browser, .. }
+            | BrowsercookieError::Json { browser, .. }
+            | BrowsercookieError::UnsupportedBrowser(browser) => Some(*browser),
+            BrowsercookieError::Io { browser, .. } => *browser,
+            BrowsercookieError::InvalidRecovery { .. } => Some(Browser::Firefox),
+            BrowsercookieError::InvalidCookieFile { .. } => None,
+        }
+    }
+
+    /// The file or directory the error is about, if any
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            BrowsercookieError::ProfileMissing { path, .. } => path.as_deref(),
+            BrowsercookieError::InvalidProfile { path, .. }
+            | BrowsercookieError::InvalidCookieStore { path, .. }
+            | BrowsercookieError::DatabaseLocked { path, .. }
+            | BrowsercookieError::UnsupportedSchema { path, .. }
+            | BrowsercookieError::InvalidRecovery { path, .. }
+            | BrowsercookieError::Decryption { path, .. }
+            | BrowsercookieError::Io { path, .. }
+            | BrowsercookieError::Json { path, .. }
+            | BrowsercookieError::InvalidCookieFile { path, .. } => Some(path),
+            BrowsercookieError::UnsupportedBrowser(_) => None,
+        }
+    }
+
+    // Tells a database held by the running browser apart from a broken one
+    pub(crate) fn from_sqlx(browser: Browser, path: &Path, source: sqlx::Error) -> Self {
+        let path = PathBuf::from(path);
+        let locked = source
+            .as_database_error()
+            .and_then(|e| e.code())
+            .is_some_and(|code| is_locked_code(&code));
+        if locked {
+            BrowsercookieError::DatabaseLocked {
+                browser,
+                path,
+                source,
+            }
+        } else {
+            BrowsercookieError::InvalidCookieStore {
+                browser,
+                path,
+                source,
+            }
+        }
+    }
 }
 
 impl fmt::Display for BrowsercookieError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error in fetching browsercookies")
+        if let Some(browser) = self.browser() {
+            write!(f, "{}: ", browser)?;
+        }
+        match self {
+            BrowsercookieError::ProfileMissing { path: None, .. } => {
+                write!(f, "no profile found")
+            }
+            BrowsercookieError::ProfileMissing {
+                path: Some(path), ..
+            } => write!(f, "no profile found at {}", path.display()),
+            BrowsercookieError::InvalidProfile { path, reason, .. } => {
+                write!(f, "invalid profile {}: {}", path.display(), reason)
+            }
+            BrowsercookieError::InvalidCookieStore { path, source, .. } => {
+                write!(
+                    f,
+                    "unable to read cookie database {}: {}",
+                    path.display(),
+                    source
+                )
+            }
+            BrowsercookieError::DatabaseLocked { path, .. } => write!(
+                f,
+                "cookie database {} is locked, try closing the browser",
+                path.display()
+            ),
+            BrowsercookieError::UnsupportedSchema { path, version, .. } => write!(
+                f,
+                "cookie database {} has unsupported schema version {}",
+                path.display(),
+                version
+            ),
+            BrowsercookieError::InvalidRecovery { path, reason } => {
+                write!(
+                    f,
+                    "invalid session recovery file {}: {}",
+                    path.display(),
+                    reason
+                )
+            }
+            BrowsercookieError::Decryption { path, reason, .. } => {
+                write!(
+                    f,
+                    "unable to decrypt cookies of {}: {}",
+                    path.display(),
+                    reason
+                )
+            }
+            BrowsercookieError::Io { path, source, .. } => {
+                write!(f, "unable to read {}: {}", path.display(), source)
+            }
+            BrowsercookieError::Json { path, source, .. } => {
+                write!(f, "unable to parse {}: {}", path.display(), source)
+            }
+            BrowsercookieError::UnsupportedBrowser(_) => write!(f, "browser is not supported"),
+            BrowsercookieError::InvalidCookieFile { path, line, reason } => write!(
+                f,
+                "invalid cookie file {} at line {}: {}",
+                path.display(),
+                line,
+                reason
+            ),
+        }
     }
 }
 
 // This is important for other errors to wrap this one.
 impl error::Error for BrowsercookieError {
-    fn description(&self) -> &str {
-        "Error in fetching browsercookies"
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            BrowsercookieError::InvalidCookieStore { source, .. }
+            | BrowsercookieError::DatabaseLocked { source, .. } => Some(source),
+            BrowsercookieError::Io { source, .. } => Some(source),
+            BrowsercookieError::Json { source, .. } => Some(source),
+            _ => None,
+        }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_display_names_browser_and_path() {
+        let error = BrowsercookieError::Io {
+            browser: Some(Browser::Firefox),
+            path: PathBuf::from("/profile/sessionstore-backups/recovery.jsonlz4"),
+            source: io::Error::new(io::ErrorKind::InvalidData, "corrupt lz4 block"),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "firefox: unable to read /profile/sessionstore-backups/recovery.jsonlz4: \
+             corrupt lz4 block"
+        );
+        assert_eq!(error.source().unwrap().to_string(), "corrupt lz4 block");
+    }
+
+    #[test]
+    fn test_from_sqlx() {
+        let error = BrowsercookieError::from_sqlx(
+            Browser::Chrome,
+            Path::new("/profile/Cookies"),
+            sqlx::Error::RowNotFound,
+        );
+
+        assert!(matches!(
+            error,
+            BrowsercookieError::InvalidCookieStore { .. }
+        ));
+        assert_eq!(error.browser(), Some(Browser::Chrome));
+        assert_eq!(error.path(), Some(Path::new("/profile/Cookies")));
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn test_locked_codes() {
+        for code in ["5", "6", "261", "517", "262"] {
+            assert!(is_locked_code(code), "{}", code);
+        }
+        for code in ["1", "11", "14", "266", "busy"] {
+            assert!(!is_locked_code(code), "{}", code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_from_sqlx_locked() {
+        use sqlx::sqlite::SqliteConnectOptions;
+        use sqlx::{Connection, Executor, SqliteConnection};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Cookies");
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true);
+        let mut browser = SqliteConnection::connect_with(&options).await.unwrap();
+        browser
+            .execute("CREATE TABLE cookies (name TEXT); BEGIN EXCLUSIVE;")
+            .await
+            .unwrap();
 
-    fn cause(&self) -> Option<&dyn error::Error> {
-        // Generic error, underlying cause isn't tracked.
-        None
+        let mut reader =
+            SqliteConnection::connect_with(&options.busy_timeout(std::time::Duration::ZERO))
+                .await
+                .unwrap();
+        let source = reader.execute("SELECT * FROM cookies").await.unwrap_err();
+        let error = BrowsercookieError::from_sqlx(Browser::Chrome, &path, source);
+        assert!(matches!(error, BrowsercookieError::DatabaseLocked { .. }));
+        assert!(error.to_string().contains("is locked"));
     }
 }
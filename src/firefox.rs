@@ -1,23 +1,27 @@
 use byteorder::{LittleEndian, ReadBytesExt};
-use cookie::{Cookie, CookieJar};
+use cookie::time::OffsetDateTime;
+use cookie::{Cookie, SameSite};
 #[allow(unused_imports)]
 use dirs::home_dir;
 use futures::TryStreamExt;
 use ini::Ini;
 use lz4::block::decompress;
 use memmap::MmapOptions;
-use regex::Regex;
 use serde_json::Value;
 use sqlx::prelude::*;
-use sqlx::sqlite::SqliteConnectOptions;
 use sqlx::SqliteConnection;
-use std::error::Error;
-use std::fs::File;
-use std::io::Cursor;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Cursor, ErrorKind};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use url::form_urlencoded;
 
+use crate::cookies::{BrowserCookie, BrowserCookies, PartitionKey};
 use crate::errors::BrowsercookieError;
-use crate::Attribute;
+use crate::snapshot;
+use crate::{Browser, Profile};
 
 #[allow(non_snake_case)]
 #[derive(Deserialize, Debug)]
@@ -32,19 +36,62 @@ struct MozCookie {
 
     #[serde(default)]
     httponly: bool,
+
+    #[serde(default)]
+    originAttributes: MozOriginAttributes,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug, Default)]
+struct MozOriginAttributes {
+    #[serde(default)]
+    userContextId: u32,
+
+    #[serde(default)]
+    partitionKey: String,
+
+    #[serde(default)]
+    firstPartyDomain: String,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+struct ContainerIdentity {
+    userContextId: u32,
+    name: Option<String>,
+    l10nID: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ContainersFile {
+    identities: Vec<ContainerIdentity>,
 }
 
+// Session archives holding session cookies, relative to the profile. Firefox
+// writes recovery.jsonlz4 while running, moving the previous one to
+// recovery.baklz4, and sessionstore.jsonlz4 on a clean shutdown, which becomes
+// previous.jsonlz4 on the next start.
+const SESSION_FILES: [&str; 4] = [
+    "sessionstore-backups/recovery.jsonlz4",
+    "sessionstore-backups/recovery.baklz4",
+    "sessionstore.jsonlz4",
+    "sessionstore-backups/previous.jsonlz4",
+];
+
+// Container names by userContextId, as set in the profile's containers.json
+type Containers = HashMap<u32, String>;
+
 #[cfg(test)]
-fn get_master_profile_path() -> PathBuf {
+fn get_master_profile_path() -> Option<PathBuf> {
     // Only used for tests, should do this a better way by mocking
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     path.push("tests/resources/profiles.ini");
-    path
+    Some(path)
 }
 
 #[cfg(not(test))]
-fn get_master_profile_path() -> PathBuf {
-    let mut path = home_dir().expect("Unable to find home directory");
+fn get_master_profile_path() -> Option<PathBuf> {
+    let mut path = home_dir()?;
     if cfg!(target_os = "macos") {
         path.push("Library/Application Support/Firefox/profiles.ini");
     } else if cfg!(target_os = "linux") {
@@ -52,173 +99,441 @@ fn get_master_profile_path() -> PathBuf {
     } else if cfg!(target_os = "windows") {
         path.push("AppData\\Roaming\\Mozilla\\Firefox\\profiles.ini");
     }
-    path
+    Some(path)
 }
 
-fn get_default_profile_path(master_profile: &Path) -> Result<PathBuf, Box<dyn Error>> {
-    let profiles_conf: Ini;
-    let mut default_profile_path = PathBuf::from(master_profile);
-    default_profile_path.pop();
+fn resolve_profile_path(profiles_dir: &Path, path: &str, is_relative: bool) -> PathBuf {
+    // profiles.ini copied from Windows separates directories with backslashes
+    let path = if cfg!(target_os = "windows") {
+        PathBuf::from(path)
+    } else {
+        PathBuf::from(path.replace('\\', "/"))
+    };
+    if is_relative {
+        profiles_dir.join(path)
+    } else {
+        path
+    }
+}
 
-    match Ini::load_from_file(master_profile) {
-        Err(_) => {
-            return Err(Box::new(BrowsercookieError::InvalidProfile(String::from(
-                "Unable to parse firefox ini profile",
-            ))))
+fn get_profiles(master_profile: &Path) -> Result<Vec<Profile>, BrowsercookieError> {
+    // Every [Profile*] section is a profile. The default ones are those named by
+    // the [Install*] sections, or the one with Default=1 for older installs.
+    // Firefox doesn't escape values, so backslashes in paths are taken literally
+    let profiles_conf = Ini::load_from_file_noescape(master_profile).map_err(|e| {
+        BrowsercookieError::InvalidProfile {
+            browser: Browser::Firefox,
+            path: PathBuf::from(master_profile),
+            reason: e.to_string(),
         }
-        Ok(p) => profiles_conf = p,
-    }
+    })?;
+    let mut profiles_dir = PathBuf::from(master_profile);
+    profiles_dir.pop();
+
+    let mut profiles: Vec<Profile> = profiles_conf
+        .iter()
+        .filter(|(sec, _)| sec.is_some_and(|s| s.starts_with("Profile")))
+        .filter_map(|(_, section)| {
+            let path = section.get("Path")?;
+            // Firefox writes IsRelative=0 for profiles created outside its directory
+            let is_relative = match section.get("IsRelative") {
+                Some(is_relative) => is_relative != "0",
+                None => !Path::new(path).is_absolute(),
+            };
+            Some(Profile {
+                name: String::from(section.get("Name").unwrap_or(path)),
+                path: resolve_profile_path(&profiles_dir, path, is_relative),
+                is_default: section.get("Default") == Some("1"),
+                install_hash: None,
+            })
+        })
+        .collect();
 
-    let default_install_profile_directory = &profiles_conf
+    let installs: Vec<(&str, &str)> = profiles_conf
         .iter()
-        .find(|&section| section.0.as_ref().is_some_and(|s| s.starts_with("Install")))
-        .and_then(|section| {
-            section
-                .1
-                .iter()
-                .find(|&(key, _)| key == "Default")
-                .map(|s| s.1)
+        .filter_map(|(sec, section)| Some((sec?.strip_prefix("Install")?, section.get("Default")?)))
+        .collect();
+
+    if !installs.is_empty() {
+        for profile in &mut profiles {
+            profile.is_default = false;
+        }
+    }
+    for (install_hash, path) in installs {
+        let path = resolve_profile_path(&profiles_dir, path, !Path::new(path).is_absolute());
+        match profiles.iter_mut().find(|p| p.path == path) {
+            Some(profile) => {
+                profile.is_default = true;
+                profile.install_hash = Some(String::from(install_hash));
+            }
+            // An install can point at a profile without a [Profile*] section
+            None => profiles.push(Profile {
+                name: path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                path,
+                is_default: true,
+                install_hash: Some(String::from(install_hash)),
+            }),
+        }
+    }
+    Ok(profiles)
+}
+
+pub(crate) fn profiles(master_path: Option<&Path>) -> Result<Vec<Profile>, BrowsercookieError> {
+    let master_profile_path = match master_path {
+        Some(path) => PathBuf::from(path),
+        // Without a home directory there is nowhere to look
+        None => get_master_profile_path().ok_or(BrowsercookieError::ProfileMissing {
+            browser: Browser::Firefox,
+            path: None,
+        })?,
+    };
+    if !master_profile_path.exists() {
+        return Err(BrowsercookieError::ProfileMissing {
+            browser: Browser::Firefox,
+            path: Some(master_profile_path),
         });
+    }
+    get_profiles(&master_profile_path)
+}
 
-    if default_install_profile_directory.is_some() {
-        default_profile_path.push(default_install_profile_directory.unwrap());
-        return Ok(default_profile_path);
+fn get_same_site(same_site: i64) -> Option<SameSite> {
+    // nsICookie::SAMESITE_NONE, SAMESITE_LAX and SAMESITE_STRICT
+    match same_site {
+        0 => Some(SameSite::None),
+        1 => Some(SameSite::Lax),
+        2 => Some(SameSite::Strict),
+        _ => None,
     }
+}
 
-    for (sec, _) in &profiles_conf {
-        let section = profiles_conf
-            .section(sec)
-            .ok_or("Invalid profile section")?;
-        match section.get("Default").and(section.get("Path")) {
-            Some(path) => {
-                default_profile_path.push(path);
-                break;
-            }
-            None => continue,
+fn get_expiry(expiry: i64) -> Option<OffsetDateTime> {
+    // Newer Firefox versions store milliseconds instead of seconds, which is
+    // easy to tell apart as seconds would be thousands of years from now.
+    let seconds = if expiry > 100_000_000_000 {
+        expiry / 1000
+    } else {
+        expiry
+    };
+    OffsetDateTime::from_unix_timestamp(seconds).ok()
+}
+
+fn get_time(microseconds: i64) -> Option<OffsetDateTime> {
+    OffsetDateTime::from_unix_timestamp_nanos(i128::from(microseconds) * 1000).ok()
+}
+
+fn get_origin_attributes(origin_attributes: &str) -> MozOriginAttributes {
+    // Serialized like "^userContextId=2&partitionKey=%28https%2Cexample.com%29",
+    // empty by default
+    let attributes = origin_attributes.strip_prefix('^').unwrap_or("");
+    let mut parsed = MozOriginAttributes::default();
+    for (key, value) in form_urlencoded::parse(attributes.as_bytes()) {
+        match key.as_ref() {
+            "userContextId" => parsed.userContextId = value.parse().unwrap_or(0),
+            "partitionKey" => parsed.partitionKey = value.into_owned(),
+            "firstPartyDomain" => parsed.firstPartyDomain = value.into_owned(),
+            _ => (),
         }
     }
-    Ok(default_profile_path)
+    parsed
+}
+
+fn get_partition_key(origin_attributes: &MozOriginAttributes) -> Option<PartitionKey> {
+    // Total Cookie Protection keys are "(scheme,site[,port][,f])", f marking a
+    // cross-site ancestor. First-party isolation only keeps the site.
+    if !origin_attributes.partitionKey.is_empty() {
+        let fields: Vec<&str> = origin_attributes
+            .partitionKey
+            .strip_prefix('(')?
+            .strip_suffix(')')?
+            .split(',')
+            .collect();
+        let (scheme, site) = match fields.as_slice() {
+            [scheme, site, ..] => (scheme, site),
+            _ => return None,
+        };
+        return Some(PartitionKey {
+            scheme: Some(String::from(*scheme)),
+            site: String::from(*site),
+            // Older versions didn't record it
+            cross_site_ancestor: fields[2..].contains(&"f").then_some(true),
+        });
+    }
+    if !origin_attributes.firstPartyDomain.is_empty() {
+        return Some(PartitionKey {
+            scheme: None,
+            site: origin_attributes.firstPartyDomain.clone(),
+            cross_site_ancestor: None,
+        });
+    }
+    None
+}
+
+fn get_container(containers: &Containers, user_context_id: u32) -> Option<String> {
+    // 0 is the default context, outside of any container
+    if user_context_id == 0 {
+        return None;
+    }
+    Some(
+        containers
+            .get(&user_context_id)
+            .cloned()
+            .unwrap_or_else(|| user_context_id.to_string()),
+    )
+}
+
+fn load_containers(containers_path: &Path) -> Result<Containers, BrowsercookieError> {
+    // Firefox only writes containers.json once containers were used
+    let content = match fs::read(containers_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Containers::new()),
+        Err(source) => {
+            return Err(BrowsercookieError::Io {
+                browser: Some(Browser::Firefox),
+                path: PathBuf::from(containers_path),
+                source,
+            })
+        }
+    };
+    let containers_file: ContainersFile =
+        serde_json::from_slice(&content).map_err(|source| BrowsercookieError::Json {
+            browser: Browser::Firefox,
+            path: PathBuf::from(containers_path),
+            source,
+        })?;
+    // The built-in containers have a localization id, like
+    // "userContextWork.label", until the user renames them
+    Ok(containers_file
+        .identities
+        .into_iter()
+        .filter_map(|identity| {
+            let l10n_id = identity.l10nID;
+            let name = identity.name.or_else(|| {
+                let name = l10n_id
+                    .as_deref()?
+                    .strip_prefix("userContext")?
+                    .strip_suffix(".label")?;
+                Some(String::from(name))
+            })?;
+            Some((identity.userContextId, name))
+        })
+        .collect())
 }
 
 async fn load_from_sqlite(
     sqlite_path: &Path,
-    cookie_jar: &mut CookieJar,
-    domain_regex: &(Regex, Attribute),
-) -> Result<(), Box<dyn Error>> {
-    let options = SqliteConnectOptions::new()
-        .filename(sqlite_path)
-        .read_only(true)
-        .immutable(true);
-    let mut conn = SqliteConnection::connect_with(&options)
-        .await
-        .expect("Could not connect to cookies.sqlite");
-    let mut query = sqlx::query("SELECT name, value, host from moz_cookies").fetch(&mut conn);
-
-    while let Some(row) = query.try_next().await? {
+    containers: &Containers,
+    cookies: &mut BrowserCookies,
+    snapshot: bool,
+) -> Result<(), BrowsercookieError> {
+    let mut database = snapshot::open(&Browser::Firefox, sqlite_path, snapshot).await?;
+    let result = read_cookies(&mut database.conn, sqlite_path, containers, cookies).await;
+    database.close().await;
+    result
+}
+
+async fn read_cookies(
+    conn: &mut SqliteConnection,
+    sqlite_path: &Path,
+    containers: &Containers,
+    cookies: &mut BrowserCookies,
+) -> Result<(), BrowsercookieError> {
+    let db_error = |e| BrowsercookieError::from_sqlx(Browser::Firefox, sqlite_path, e);
+    let mut query = sqlx::query(
+        "SELECT name, value, host, path, expiry, isSecure, isHttpOnly, sameSite, \
+         creationTime, lastAccessed, originAttributes FROM moz_cookies",
+    )
+    .fetch(conn);
+
+    while let Some(row) = query.try_next().await.map_err(db_error)? {
         let name: String = row.get(0);
         let value: String = row.get(1);
         let host: String = row.get(2);
+        let path: Option<String> = row.get(3);
+        let expiry: Option<i64> = row.get(4);
+        let secure: Option<bool> = row.get(5);
+        let http_only: Option<bool> = row.get(6);
+        let same_site: Option<i64> = row.get(7);
+        let creation_time: Option<i64> = row.get(8);
+        let last_accessed: Option<i64> = row.get(9);
+        let origin_attributes: Option<String> = row.get(10);
 
-        if domain_regex.0.is_match(&host) {
-            cookie_jar.add(
-                Cookie::build((name, value))
-                    .domain(host)
-                    .path("/")
-                    .secure(false)
-                    .http_only(false)
-                    .build(),
-            );
+        let host_only = !host.starts_with('.');
+        let mut cookie = Cookie::build((name, value))
+            .domain(host)
+            .path(path.unwrap_or_else(|| String::from("/")))
+            .secure(secure.unwrap_or(false))
+            .http_only(http_only.unwrap_or(false));
+        if let Some(expires) = expiry.and_then(get_expiry) {
+            cookie = cookie.expires(expires);
+        }
+        if let Some(same_site) = same_site.and_then(get_same_site) {
+            cookie = cookie.same_site(same_site);
         }
+        let mut browser_cookie = BrowserCookie::new(cookie.build());
+        browser_cookie.host_only = host_only;
+        browser_cookie.creation_time = creation_time.and_then(get_time);
+        browser_cookie.last_accessed = last_accessed.and_then(get_time);
+        let origin_attributes = get_origin_attributes(origin_attributes.as_deref().unwrap_or(""));
+        browser_cookie.container = get_container(containers, origin_attributes.userContextId);
+        browser_cookie.partition_key = get_partition_key(&origin_attributes);
+        cookies.add(browser_cookie);
     }
     Ok(())
 }
 
+fn get_session_paths(profile_path: &Path) -> Vec<PathBuf> {
+    // Newest first, in the order of SESSION_FILES for equal times
+    let mut session_paths: Vec<(PathBuf, Option<SystemTime>)> = SESSION_FILES
+        .iter()
+        .map(|f| profile_path.join(f))
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            Some((path, metadata.modified().ok()))
+        })
+        .collect();
+    session_paths.sort_by_key(|(_, modified)| Reverse(*modified));
+    session_paths.into_iter().map(|(path, _)| path).collect()
+}
+
 async fn load_from_recovery(
     recovery_path: &Path,
-    cookie_jar: &mut CookieJar,
-    regex_and_attribute: &(Regex, Attribute),
-) -> Result<(), Box<dyn Error>> {
-    let recovery_file = File::open(recovery_path)?;
-    let recovery_mmap = unsafe { MmapOptions::new().map(&recovery_file)? };
-
-    if recovery_mmap.len() <= 8
-        || recovery_mmap.get(0..8).ok_or("Invalid recovery")? != "mozLz40\0".as_bytes()
-    {
-        return Err(Box::new(BrowsercookieError::InvalidRecovery(String::from(
-            "Firefox invalid recovery archive",
-        ))));
+    containers: &Containers,
+    cookies: &mut BrowserCookies,
+) -> Result<(), BrowsercookieError> {
+    let io_error = |source| BrowsercookieError::Io {
+        browser: Some(Browser::Firefox),
+        path: PathBuf::from(recovery_path),
+        source,
+    };
+    let invalid = |reason: &str| BrowsercookieError::InvalidRecovery {
+        path: PathBuf::from(recovery_path),
+        reason: String::from(reason),
+    };
+
+    let recovery_file = File::open(recovery_path).map_err(io_error)?;
+    // Session cookies carry no times, the archive was last written with them
+    // in the browser, which is when they were last known to be in use
+    let saved_at = recovery_file
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(OffsetDateTime::from);
+    let recovery_mmap = unsafe { MmapOptions::new().map(&recovery_file).map_err(io_error)? };
+
+    if recovery_mmap.len() < 12 || &recovery_mmap[0..8] != "mozLz40\0".as_bytes() {
+        return Err(invalid("not a mozLz4 archive"));
     }
 
-    let mut rdr = Cursor::new(recovery_mmap.get(8..12).ok_or("Invalid recovery")?);
+    let mut rdr = Cursor::new(&recovery_mmap[8..12]);
     let uncompressed_size = rdr.read_i32::<LittleEndian>().ok();
 
-    let recovery_json_bytes = decompress(
-        recovery_mmap.get(12..).ok_or("Invalid recovery")?,
-        uncompressed_size,
-    )?;
+    let recovery_json_bytes =
+        decompress(&recovery_mmap[12..], uncompressed_size).map_err(io_error)?;
 
-    let recovery_json: Value = serde_json::from_slice(&recovery_json_bytes)?;
+    let recovery_json: Value = serde_json::from_slice(&recovery_json_bytes).map_err(|source| {
+        BrowsercookieError::Json {
+            browser: Browser::Firefox,
+            path: PathBuf::from(recovery_path),
+            source,
+        }
+    })?;
     for c in recovery_json["cookies"]
         .as_array()
-        .ok_or("Invalid recovery")?
+        .ok_or_else(|| invalid("no cookies list"))?
     {
         if let Ok(cookie) =
             serde_json::from_value(c.clone()) as Result<MozCookie, serde_json::error::Error>
         {
             // println!("Loading for {}: {}={}", cookie.host, cookie.name, cookie.value);
-            if regex_and_attribute.0.is_match(&cookie.host) {
-                cookie_jar.add(
-                    Cookie::build((cookie.name, cookie.value))
-                        .domain(cookie.host)
-                        .path(cookie.path)
-                        .secure(cookie.secure)
-                        .http_only(cookie.httponly)
-                        .build(),
-                );
-            }
+            let host_only = !cookie.host.starts_with('.');
+            let mut browser_cookie = BrowserCookie::new(
+                Cookie::build((cookie.name, cookie.value))
+                    .domain(cookie.host)
+                    .path(cookie.path)
+                    .secure(cookie.secure)
+                    .http_only(cookie.httponly)
+                    .build(),
+            );
+            browser_cookie.host_only = host_only;
+            browser_cookie.container =
+                get_container(containers, cookie.originAttributes.userContextId);
+            browser_cookie.partition_key = get_partition_key(&cookie.originAttributes);
+            browser_cookie.last_accessed = saved_at;
+            cookies.add(browser_cookie);
         }
     }
     Ok(())
 }
 
 pub(crate) async fn load(
-    cookie_jar: &mut CookieJar,
-    regex_and_attribute: &(Regex, Attribute),
-    master_path: Option<&Path>,
-) -> Result<(), Box<dyn Error>> {
-    // Returns a CookieJar if following steps go right
-    //
-    // 1. Get default profile path for firefox from master ini profiles config.
-    // 2. Load cookies from recovery json (sessionstore-backups/recovery.jsonlz4)
-    //    of the default profile.
-    let master_profile_path = if let Some(path) = master_path {
-        PathBuf::from(path)
-    } else {
-        get_master_profile_path()
-    };
-    if !master_profile_path.exists() {
-        return Err(Box::new(BrowsercookieError::ProfileMissing(String::from(
-            "Firefox profile path doesn't exist",
-        ))));
-    }
-
-    let profile_path = get_default_profile_path(&master_profile_path)?;
+    cookies: &mut BrowserCookies,
+    profiles: &[Profile],
+    snapshot: bool,
+    errors: &mut Vec<BrowsercookieError>,
+) {
+    // Loads session cookies from the newest valid session file (see
+    // SESSION_FILES) and cookies from cookies.sqlite of each profile, cookies
+    // in both are merged by the merge policy of `cookies`. Files that can't be
+    // read are reported in errors and skipped.
+    for profile in profiles {
+        if !profile.path.is_dir() {
+            errors.push(BrowsercookieError::InvalidProfile {
+                browser: Browser::Firefox,
+                path: profile.path.clone(),
+                reason: format!("profile {} doesn't exist", profile.name),
+            });
+            continue;
+        }
 
-    let mut recovery_path = profile_path.clone();
-    recovery_path.push("sessionstore-backups/recovery.jsonlz4");
+        let containers = match load_containers(&profile.path.join("containers.json")) {
+            Ok(containers) => containers,
+            Err(e) => {
+                // Cookies still load, named by their container id
+                errors.push(e);
+                Containers::new()
+            }
+        };
 
-    if recovery_path.exists() {
-        load_from_recovery(&recovery_path, cookie_jar, regex_and_attribute).await?;
-    }
+        // Older archives are only errors when none of them could be read
+        let mut session_errors = vec![];
+        for session_path in get_session_paths(&profile.path) {
+            let mut session_cookies = BrowserCookies::new();
+            match load_from_recovery(&session_path, &containers, &mut session_cookies).await {
+                Ok(()) => {
+                    cookies.add_from_source(
+                        session_cookies,
+                        Some(Browser::Firefox),
+                        Some(profile),
+                        &session_path,
+                    );
+                    session_errors.clear();
+                    break;
+                }
+                Err(e) => session_errors.push(e),
+            }
+        }
+        errors.append(&mut session_errors);
 
-    let mut sqlite_path = profile_path.clone();
+        let sqlite_path = profile.path.join("cookies.sqlite");
 
-    if sqlite_path.exists() {
-        sqlite_path.push("cookies.sqlite");
-        load_from_sqlite(&sqlite_path, cookie_jar, regex_and_attribute).await?;
+        if sqlite_path.exists() {
+            let mut sqlite_cookies = BrowserCookies::new();
+            if let Err(e) =
+                load_from_sqlite(&sqlite_path, &containers, &mut sqlite_cookies, snapshot).await
+            {
+                errors.push(e);
+            }
+            cookies.add_from_source(
+                sqlite_cookies,
+                Some(Browser::Firefox),
+                Some(profile),
+                &sqlite_path,
+            );
+        }
     }
-
-    Ok(())
 }
 
 #[cfg(test)]
@@ -229,10 +544,9 @@ mod tests {
     async fn test_recovery_load() {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         path.push("tests/resources/recovery.jsonlz4");
-        let mut bcj = Box::new(CookieJar::new());
+        let mut bcj = Box::new(BrowserCookies::new());
 
-        let domain_re = Regex::new(".*").unwrap();
-        load_from_recovery(&path, &mut bcj, &(domain_re, Attribute::Domain))
+        load_from_recovery(&path, &Containers::new(), &mut bcj)
             .await
             .expect("Failed to load from firefox recovery json");
 
@@ -251,9 +565,8 @@ mod tests {
     async fn test_sqlite_load() {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         path.push("tests/resources/Profiles/1qbuu7ux.default/cookies.sqlite");
-        let domain_re = Regex::new(".*").unwrap();
-        let mut bcj = Box::new(CookieJar::new());
-        load_from_sqlite(&path, &mut bcj, &(domain_re, Attribute::Domain))
+        let mut bcj = Box::new(BrowserCookies::new());
+        load_from_sqlite(&path, &Containers::new(), &mut bcj, false)
             .await
             .unwrap();
 
@@ -262,6 +575,354 @@ mod tests {
         assert_eq!(cookie.value(), "somevalue");
         assert_eq!(cookie.path(), Some("/"));
         assert_eq!(cookie.domain(), Some("somehost"));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(
+            cookie.expires_datetime(),
+            OffsetDateTime::from_unix_timestamp(2006424037).ok()
+        );
+        assert_eq!(
+            cookie.creation_time(),
+            OffsetDateTime::from_unix_timestamp_nanos(1691064037404867000).ok()
+        );
+        assert_eq!(cookie.last_accessed(), cookie.creation_time());
+
+        let session_cookie = bcj.get("othername").unwrap();
+
+        assert_eq!(session_cookie.secure(), Some(false));
+        assert_eq!(session_cookie.http_only(), Some(false));
+        assert_eq!(session_cookie.expires(), None);
+    }
+
+    #[tokio::test]
+    async fn test_load_reports_corrupt_files() {
+        let mut profile_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        profile_path.push("tests/resources/Profiles/brokenxx.corrupt");
+        let profiles = vec![Profile {
+            name: String::from("corrupt"),
+            path: profile_path.clone(),
+            is_default: true,
+            install_hash: None,
+        }];
+
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut errors = vec![];
+        load(&mut bcj, &profiles, false, &mut errors).await;
+
+        assert!(bcj.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].browser(), Some(Browser::Firefox));
+        assert_eq!(
+            errors[0].path(),
+            Some(profile_path.join("cookies.sqlite").as_path())
+        );
+        assert!(matches!(
+            errors[0],
+            BrowsercookieError::InvalidCookieStore { .. }
+        ));
+        assert!(errors[0]
+            .to_string()
+            .contains("brokenxx.corrupt/cookies.sqlite"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_recovery() {
+        let recovery_dir = tempfile::tempdir().unwrap();
+        let recovery_path = recovery_dir.path().join("recovery.jsonlz4");
+        std::fs::write(&recovery_path, "not a mozLz4 archive").unwrap();
+
+        let mut bcj = Box::new(BrowserCookies::new());
+        let result = load_from_recovery(&recovery_path, &Containers::new(), &mut bcj).await;
+
+        let error = result.unwrap_err();
+        assert!(matches!(error, BrowsercookieError::InvalidRecovery { .. }));
+        assert_eq!(error.path(), Some(recovery_path.as_path()));
+    }
+
+    #[tokio::test]
+    async fn test_containers() {
+        let mut profile_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        profile_path.push("tests/resources/Profiles/c0nt41nr.containers");
+        let profiles = vec![Profile {
+            name: String::from("containers"),
+            path: profile_path,
+            is_default: true,
+            install_hash: None,
+        }];
+
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut errors = vec![];
+        load(&mut bcj, &profiles, false, &mut errors).await;
+
+        assert!(errors.is_empty());
+        let mut tenants: Vec<(Option<&str>, &str)> = bcj
+            .get_all("tenant")
+            .map(|c| (c.container(), c.value()))
+            .collect();
+        tenants.sort_unstable();
+        assert_eq!(
+            tenants,
+            [
+                (None, "none"),
+                (Some("9"), "unknown"),
+                (Some("Customer A"), "customer-a"),
+                (Some("Customer B"), "customer-b"),
+                (Some("Work"), "work"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_origin_attributes() {
+        assert_eq!(get_origin_attributes("").userContextId, 0);
+        assert_eq!(get_origin_attributes("^userContextId=2").userContextId, 2);
+        let origin_attributes = get_origin_attributes(
+            "^privateBrowsingId=1&userContextId=12&partitionKey=%28https%2Cexample.com%2C8443%2Cf%29",
+        );
+        assert_eq!(origin_attributes.userContextId, 12);
+        assert_eq!(
+            get_partition_key(&origin_attributes),
+            Some(PartitionKey {
+                scheme: Some(String::from("https")),
+                site: String::from("example.com"),
+                cross_site_ancestor: Some(true),
+            })
+        );
+
+        let origin_attributes = get_origin_attributes("^firstPartyDomain=example.com");
+        assert_eq!(origin_attributes.userContextId, 0);
+        assert_eq!(
+            get_partition_key(&origin_attributes).unwrap().to_string(),
+            "example.com"
+        );
+        assert_eq!(get_partition_key(&get_origin_attributes("")), None);
+    }
+
+    #[tokio::test]
+    async fn test_partitioned() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/Profiles/p4rt1t10n.partitioned/cookies.sqlite");
+        let mut bcj = Box::new(BrowserCookies::new());
+        load_from_sqlite(&path, &Containers::new(), &mut bcj, false)
+            .await
+            .unwrap();
+
+        // Same named cookies of each partition are all kept
+        assert_eq!(bcj.len(), 4);
+        let news = url::Url::parse("https://news.example/").unwrap();
+        let shop = url::Url::parse("https://shop.example/").unwrap();
+        let widget = url::Url::parse("https://widget.example/").unwrap();
+        assert_eq!(
+            bcj.header_for_url_under(&widget, &news),
+            "embed=in-news; fpi=news"
+        );
+        assert_eq!(
+            bcj.header_for_url_under(&widget, &shop),
+            "embed=in-shop; fpi=shop"
+        );
+        assert_eq!(bcj.header_for_url(&widget), "");
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_load_with_wal() {
+        use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+        use sqlx::{Connection, Executor};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cookies.sqlite");
+
+        // Stands in for a running Firefox, which keeps recent writes in the WAL
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let mut firefox = sqlx::SqliteConnection::connect_with(&options)
+            .await
+            .unwrap();
+        firefox
+            .execute(
+                "PRAGMA wal_autocheckpoint = 0; \
+                 CREATE TABLE moz_cookies (id INTEGER PRIMARY KEY, \
+                 originAttributes TEXT NOT NULL DEFAULT '', name TEXT, value TEXT, host TEXT, \
+                 path TEXT, expiry INTEGER, lastAccessed INTEGER, creationTime INTEGER, \
+                 isSecure INTEGER, isHttpOnly INTEGER, sameSite INTEGER DEFAULT 0); \
+                 INSERT INTO moz_cookies (name, value, host, path, expiry) \
+                 VALUES ('old', 'checkpointed', 'walhost', '/', 2006424037); \
+                 PRAGMA wal_checkpoint(TRUNCATE); \
+                 INSERT INTO moz_cookies (name, value, host, path, expiry) \
+                 VALUES ('session', 'fresh', 'walhost', '/', 2006424037);",
+            )
+            .await
+            .unwrap();
+
+        let mut bcj = Box::new(BrowserCookies::new());
+        load_from_sqlite(&path, &Containers::new(), &mut bcj, false)
+            .await
+            .unwrap();
+        assert_eq!(bcj.len(), 1);
+        assert!(bcj.get("session").is_none());
+
+        let mut bcj = Box::new(BrowserCookies::new());
+        load_from_sqlite(&path, &Containers::new(), &mut bcj, true)
+            .await
+            .unwrap();
+        assert_eq!(bcj.len(), 2);
+        assert_eq!(bcj.get("session").unwrap().value(), "fresh");
+
+        // The live database is left as it was, and still writable
+        firefox
+            .execute("INSERT INTO moz_cookies (name) VALUES ('later')")
+            .await
+            .unwrap();
+        firefox.close().await.unwrap();
+    }
+
+    fn write_session(path: &Path, cookie_name: &str, modified: SystemTime) {
+        write_session_cookie(path, ".sessionhost.example", cookie_name, "v", modified);
+    }
+
+    fn write_session_cookie(
+        path: &Path,
+        host: &str,
+        cookie_name: &str,
+        value: &str,
+        modified: SystemTime,
+    ) {
+        let json = format!(
+            r#"{{"cookies": [{{"host": "{}", "name": "{}", "path": "/", "value": "{}"}}]}}"#,
+            host, cookie_name, value
+        );
+        let mut archive = b"mozLz40\0".to_vec();
+        archive.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        archive.extend(lz4::block::compress(json.as_bytes(), None, false).unwrap());
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, archive).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_file_fallbacks() {
+        let profile_dir = tempfile::tempdir().unwrap();
+        let profile_path = profile_dir.path().to_path_buf();
+        let profiles = vec![Profile {
+            name: String::from("session"),
+            path: profile_path.clone(),
+            is_default: true,
+            install_hash: None,
+        }];
+        let hour_ago = SystemTime::now() - std::time::Duration::from_secs(3600);
+        let day_ago = SystemTime::now() - std::time::Duration::from_secs(86400);
+
+        // After a clean shutdown only sessionstore.jsonlz4 is up to date
+        write_session(
+            &profile_path.join("sessionstore-backups/previous.jsonlz4"),
+            "previous",
+            day_ago,
+        );
+        write_session(
+            &profile_path.join("sessionstore.jsonlz4"),
+            "shutdown",
+            hour_ago,
+        );
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut errors = vec![];
+        load(&mut bcj, &profiles, false, &mut errors).await;
+        assert!(errors.is_empty());
+        assert_eq!(bcj.len(), 1);
+        let cookie = bcj.get("shutdown").unwrap();
+        assert_eq!(
+            cookie.source_file(),
+            Some(profile_path.join("sessionstore.jsonlz4").as_path())
+        );
+        assert_eq!(
+            cookie.last_accessed(),
+            fs::metadata(profile_path.join("sessionstore.jsonlz4"))
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .map(OffsetDateTime::from)
+        );
+
+        // A recovery file cut short while being written falls back to the next newest
+        let recovery_path = profile_path.join("sessionstore-backups/recovery.jsonlz4");
+        fs::write(&recovery_path, b"mozLz40\0").unwrap();
+        write_session(
+            &profile_path.join("sessionstore-backups/recovery.baklz4"),
+            "backup",
+            hour_ago + std::time::Duration::from_secs(60),
+        );
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut errors = vec![];
+        load(&mut bcj, &profiles, false, &mut errors).await;
+        assert!(errors.is_empty());
+        assert_eq!(bcj.len(), 1);
+        assert!(bcj
+            .get("backup")
+            .unwrap()
+            .source_file()
+            .unwrap()
+            .ends_with("recovery.baklz4"));
+
+        // Errors are reported once no session file is left
+        for path in get_session_paths(&profile_path) {
+            fs::write(path, b"mozLz40\0").unwrap();
+        }
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut errors = vec![];
+        load(&mut bcj, &profiles, false, &mut errors).await;
+        assert!(bcj.is_empty());
+        assert_eq!(errors.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_session_cookie_recency() {
+        let profile_dir = tempfile::tempdir().unwrap();
+        let profile_path = profile_dir.path().to_path_buf();
+        let mut sqlite_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sqlite_path.push("tests/resources/Profiles/x7kq2m4c.work/cookies.sqlite");
+        fs::copy(&sqlite_path, profile_path.join("cookies.sqlite")).unwrap();
+        let profiles = vec![Profile {
+            name: String::from("work"),
+            path: profile_path.clone(),
+            is_default: true,
+            install_hash: None,
+        }];
+        let recovery_path = profile_path.join("sessionstore-backups/recovery.jsonlz4");
+
+        // The sqlite copy was last accessed in August 2023
+        let saved_before = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        for (saved_at, value) in [
+            (SystemTime::now(), "sessionvalue"),
+            (saved_before, "workvalue"),
+        ] {
+            write_session_cookie(
+                &recovery_path,
+                ".workhost.example",
+                "workname",
+                "sessionvalue",
+                saved_at,
+            );
+            let mut bcj = Box::new(BrowserCookies::new());
+            let mut errors = vec![];
+            load(&mut bcj, &profiles, false, &mut errors).await;
+            assert!(errors.is_empty());
+            assert_eq!(bcj.len(), 1);
+            assert_eq!(bcj.get("workname").unwrap().value(), value);
+        }
+    }
+
+    #[test]
+    fn test_expiry_in_milliseconds() {
+        assert_eq!(get_expiry(2006424037), get_expiry(2006424037000));
+        assert_eq!(
+            get_expiry(2006424037000).unwrap().unix_timestamp(),
+            2006424037
+        );
     }
 
     #[test]
@@ -269,10 +930,12 @@ mod tests {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         path.push("tests/resources/profiles.ini");
 
-        let default_profile_path =
-            get_default_profile_path(&path).expect("Failed to parse master firefox profile");
+        let profiles = get_profiles(&path).expect("Failed to parse master firefox profile");
+        let default_profile = profiles.iter().find(|p| p.is_default).unwrap();
 
-        assert!(default_profile_path.ends_with(PathBuf::from("Profiles/1qbuu7ux.default")));
+        assert!(default_profile
+            .path
+            .ends_with(PathBuf::from("Profiles/1qbuu7ux.default")));
     }
 
     #[test]
@@ -280,9 +943,92 @@ mod tests {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         path.push("tests/resources/profiles_with_install.ini");
 
-        let default_profile_path =
-            get_default_profile_path(&path).expect("Failed to parse master firefox profile");
+        let profiles = get_profiles(&path).expect("Failed to parse master firefox profile");
+        let default_profile = profiles.iter().find(|p| p.is_default).unwrap();
+
+        assert!(default_profile
+            .path
+            .ends_with(PathBuf::from("Profiles/dmjvfd1o.default-release")));
+        assert_eq!(
+            default_profile.install_hash.as_deref(),
+            Some("4F96D1932A9F858E")
+        );
+        assert_eq!(profiles.iter().filter(|p| p.is_default).count(), 1);
+    }
+
+    #[test]
+    fn test_all_profiles() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/profiles.ini");
+
+        let profiles = get_profiles(&path).expect("Failed to parse master firefox profile");
+
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].name, "default");
+        assert!(profiles[0].is_default);
+        assert_eq!(profiles[0].install_hash, None);
+        assert_eq!(profiles[1].name, "work");
+        assert!(profiles[1].path.ends_with("Profiles/x7kq2m4c.work"));
+        assert!(!profiles[1].is_default);
+    }
+
+    #[test]
+    fn test_windows_profile_path() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/profiles_windows.ini");
+
+        let profiles = get_profiles(&path).expect("Failed to parse master firefox profile");
+
+        assert_eq!(profiles.len(), 1);
+        assert!(profiles[0].path.is_dir());
+        assert!(profiles[0]
+            .path
+            .ends_with(PathBuf::from("Profiles/1qbuu7ux.default")));
+    }
+
+    #[tokio::test]
+    async fn test_absolute_profile_path() {
+        let mut profile_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        profile_path.push("tests/resources/Profiles/x7kq2m4c.work");
+        let master_dir = tempfile::tempdir().unwrap();
+        let master_path = master_dir.path().join("profiles.ini");
+        std::fs::write(
+            &master_path,
+            format!(
+                "[Profile0]\nName=elsewhere\nIsRelative=0\nPath={}\nDefault=1\n",
+                profile_path.display()
+            ),
+        )
+        .unwrap();
+
+        let profiles = get_profiles(&master_path).expect("Failed to parse master firefox profile");
+        assert_eq!(profiles[0].path, profile_path);
+
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut errors = vec![];
+        load(&mut bcj, &profiles, false, &mut errors).await;
+        assert!(errors.is_empty());
+        assert_eq!(bcj.get("workname").unwrap().value(), "workvalue");
+    }
+
+    #[tokio::test]
+    async fn test_missing_profile_directory() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/profiles_with_install.ini");
+
+        let profiles = get_profiles(&path).expect("Failed to parse master firefox profile");
+        let mut bcj = Box::new(BrowserCookies::new());
+        let mut errors = vec![];
+        load(&mut bcj, &profiles, false, &mut errors).await;
 
-        assert!(default_profile_path.ends_with(PathBuf::from("Profiles/dmjvfd1o.default-release")));
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            BrowsercookieError::InvalidProfile { .. }
+        ));
+        assert!(errors[0]
+            .path()
+            .unwrap()
+            .ends_with("Profiles/dmjvfd1o.default-release"));
     }
 }
@@ -0,0 +1,200 @@
+//! JSON and JSON Lines output of cookies, for `jq` and scripts
+//!
+//! The schema is versioned with [`SCHEMA_VERSION`], which changes whenever a
+//! field is renamed, removed or changes type. New fields may be added without
+//! a version change.
+//!
+//! A JSON document is `{"version": 1, "cookies": [...]}`. A JSON Lines stream
+//! has one cookie object per line, each with its own `"version": 1` field.
+//! Cookie objects hold:
+//!
+//! | Field                 | Type                                                                   |
+//! |-----------------------|------------------------------------------------------------------------|
+//! | `name`                | string                                                                 |
+//! | `value`               | string                                                                 |
+//! | `domain`              | string or null, without leading dot                                    |
+//! | `host_only`           | bool, false if subdomains get the cookie too                           |
+//! | `path`                | string or null                                                         |
+//! | `secure`              | bool                                                                   |
+//! | `http_only`           | bool                                                                   |
+//! | `same_site`           | `"Strict"`, `"Lax"`, `"None"` or null                                  |
+//! | `expires`             | Unix time in seconds, null for session cookies                         |
+//! | `browser`             | string (e.g. `"firefox"`), null for cookie files                       |
+//! | `profile`             | string or null                                                         |
+//! | `container`           | string, null outside of Firefox containers                             |
+//! | `partition_key`       | top-level site (e.g. `"https://example.com"`), null if not partitioned |
+//! | `cross_site_ancestor` | bool, null if not partitioned or not recorded                          |
+//! | `source_file`         | string or null                                                         |
+use serde::Serialize;
+use std::io::{self, Write};
+
+use crate::cookies::{BrowserCookie, BrowserCookies};
+
+/// Version of the schema written by this module
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct JsonCookie<'a> {
+    name: &'a str,
+    value: &'a str,
+    domain: Option<&'a str>,
+    host_only: bool,
+    path: Option<&'a str>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<String>,
+    expires: Option<i64>,
+    browser: Option<String>,
+    profile: Option<&'a str>,
+    container: Option<&'a str>,
+    partition_key: Option<String>,
+    cross_site_ancestor: Option<bool>,
+    source_file: Option<String>,
+}
+
+impl<'a> From<&'a BrowserCookie> for JsonCookie<'a> {
+    fn from(cookie: &'a BrowserCookie) -> Self {
+        JsonCookie {
+            name: cookie.name(),
+            value: cookie.value(),
+            domain: cookie.domain(),
+            host_only: cookie.is_host_only(),
+            path: cookie.path(),
+            secure: cookie.secure() == Some(true),
+            http_only: cookie.http_only() == Some(true),
+            same_site: cookie.same_site().map(|s| s.to_string()),
+            expires: cookie.expires_datetime().map(|e| e.unix_timestamp()),
+            browser: cookie.browser().map(|b| b.to_string()),
+            profile: cookie.profile(),
+            container: cookie.container(),
+            partition_key: cookie.partition_key().map(|k| k.to_string()),
+            cross_site_ancestor: cookie.partition_key().and_then(|k| k.cross_site_ancestor),
+            source_file: cookie.source_file().map(|p| p.display().to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    version: u32,
+    cookies: Vec<JsonCookie<'a>>,
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    version: u32,
+    #[serde(flatten)]
+    cookie: JsonCookie<'a>,
+}
+
+/// Writes the cookies as a single JSON document
+pub fn write_json(cookies: &BrowserCookies, writer: &mut impl Write) -> io::Result<()> {
+    let document = Document {
+        version: SCHEMA_VERSION,
+        cookies: cookies.iter().map(JsonCookie::from).collect(),
+    };
+    serde_json::to_writer_pretty(&mut *writer, &document)?;
+    writer.write_all(b"\n")
+}
+
+/// Writes the cookies as JSON Lines, one cookie object per line
+pub fn write_jsonl(cookies: &BrowserCookies, writer: &mut impl Write) -> io::Result<()> {
+    for cookie in cookies {
+        let line = Line {
+            version: SCHEMA_VERSION,
+            cookie: JsonCookie::from(cookie),
+        };
+        serde_json::to_writer(&mut *writer, &line)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Browser, CookieFinder};
+    use serde_json::{json, Value};
+
+    async fn chrome_cookies() -> BrowserCookies {
+        CookieFinder::builder()
+            .with_browser(Browser::Chrome)
+            .with_regexp(
+                regex::Regex::new("^chromename$").unwrap(),
+                crate::Attribute::Name,
+            )
+            .with_key_provider(crate::keyring::StaticPassword::new("testpassword"))
+            .build()
+            .find()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_write_json() {
+        let cookies = chrome_cookies().await;
+        let mut buffer = Vec::new();
+        write_json(&cookies, &mut buffer).unwrap();
+
+        let document: Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(document["version"], 1);
+        let cookie = &document["cookies"][0];
+        assert_eq!(cookie["name"], "chromename");
+        assert_eq!(cookie["value"], "chromevalue");
+        assert_eq!(cookie["domain"], "chromehost.example");
+        assert_eq!(cookie["host_only"], false);
+        assert_eq!(cookie["secure"], true);
+        assert_eq!(cookie["http_only"], true);
+        assert_eq!(cookie["same_site"], Value::Null);
+        assert_eq!(cookie["expires"], Value::Null);
+        assert_eq!(cookie["browser"], "chrome");
+        assert_eq!(cookie["profile"], "Person 1");
+        assert!(cookie["source_file"]
+            .as_str()
+            .unwrap()
+            .ends_with("google-chrome/Default/Network/Cookies"));
+    }
+
+    #[tokio::test]
+    async fn test_write_jsonl() {
+        let mut cookies = chrome_cookies().await;
+        cookies.add(
+            cookie::Cookie::build(("other", "value"))
+                .domain("example.com")
+                .path("/")
+                .build(),
+        );
+        let mut buffer = Vec::new();
+        write_jsonl(&cookies, &mut buffer).unwrap();
+
+        let lines: Vec<Value> = String::from_utf8(buffer)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["version"], 1);
+        assert_eq!(lines[0]["name"], "chromename");
+        assert_eq!(
+            lines[1],
+            json!({
+                "version": 1,
+                "name": "other",
+                "value": "value",
+                "domain": "example.com",
+                "host_only": false,
+                "path": "/",
+                "secure": false,
+                "http_only": false,
+                "same_site": null,
+                "expires": null,
+                "browser": null,
+                "profile": null,
+                "container": null,
+                "partition_key": null,
+                "cross_site_ancestor": null,
+                "source_file": null,
+            })
+        );
+    }
+}
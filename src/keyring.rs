@@ -0,0 +1,146 @@
+//! Providers for the Chromium "Safe Storage" password
+//!
+//! Chromium encrypts `v11` cookie values with a key derived from a per-user
+//! secret kept in GNOME Keyring or KWallet. A [`KeyProvider`] hands that secret
+//! to the cookie finder, so it can decrypt those values.
+//!
+//! ```rust,ignore
+//! use browsercookie::keyring::CommandPassword;
+//!
+//! let cookies = CookieFinder::builder()
+//!     .with_key_provider(CommandPassword::new(
+//!         "secret-tool",
+//!         &["lookup", "application", "{application}"],
+//!     ))
+//!     .build()
+//!     .find()
+//!     .await;
+//! ```
+use std::env;
+use std::process::Command;
+
+/// Source of the Safe Storage password used for `v11` encrypted cookies
+///
+/// A provider is asked at most once per browser and search, when the first
+/// `v11` value is found, and on a thread where it may block. One that panics
+/// is taken as not knowing the password.
+pub trait KeyProvider: Send + Sync {
+    /// Returns the password stored for a keyring application (e.g. "chrome"),
+    /// or `None` if this provider doesn't know it.
+    fn password(&self, application: &str) -> Option<String>;
+}
+
+/// Always returns the same password
+pub struct StaticPassword {
+    password: String,
+}
+
+impl StaticPassword {
+    pub fn new(password: &str) -> Self {
+        StaticPassword {
+            password: String::from(password),
+        }
+    }
+}
+
+impl KeyProvider for StaticPassword {
+    fn password(&self, _application: &str) -> Option<String> {
+        Some(self.password.clone())
+    }
+}
+
+/// Reads the password from an environment variable
+pub struct EnvPassword {
+    variable: String,
+}
+
+impl EnvPassword {
+    pub fn new(variable: &str) -> Self {
+        EnvPassword {
+            variable: String::from(variable),
+        }
+    }
+}
+
+impl KeyProvider for EnvPassword {
+    fn password(&self, _application: &str) -> Option<String> {
+        env::var(&self.variable).ok()
+    }
+}
+
+/// Runs an external command and uses its standard output as the password
+///
+/// Any `{application}` argument is replaced with the keyring application
+/// being looked up, so one provider can serve several browsers.
+pub struct CommandPassword {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandPassword {
+    pub fn new(program: &str, args: &[&str]) -> Self {
+        CommandPassword {
+            program: String::from(program),
+            args: args.iter().map(|a| String::from(*a)).collect(),
+        }
+    }
+}
+
+impl KeyProvider for CommandPassword {
+    fn password(&self, application: &str) -> Option<String> {
+        let output = Command::new(&self.program)
+            .args(
+                self.args
+                    .iter()
+                    .map(|a| a.replace("{application}", application)),
+            )
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let password = String::from_utf8(output.stdout).ok()?;
+        let password = password.trim_end_matches(['\r', '\n']);
+        if password.is_empty() {
+            None
+        } else {
+            Some(String::from(password))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_password() {
+        let provider = StaticPassword::new("secret");
+        assert_eq!(provider.password("chrome"), Some(String::from("secret")));
+    }
+
+    #[test]
+    fn test_env_password() {
+        env::set_var("BROWSERCOOKIE_TEST_PASSWORD", "secret");
+        assert_eq!(
+            EnvPassword::new("BROWSERCOOKIE_TEST_PASSWORD").password("chrome"),
+            Some(String::from("secret"))
+        );
+        assert_eq!(
+            EnvPassword::new("BROWSERCOOKIE_TEST_MISSING").password("chrome"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_command_password() {
+        let provider = CommandPassword::new("echo", &["{application}-secret"]);
+        assert_eq!(
+            provider.password("chrome"),
+            Some(String::from("chrome-secret"))
+        );
+
+        let failing = CommandPassword::new("false", &[]);
+        assert_eq!(failing.password("chrome"), None);
+    }
+}
@@ -1,60 +1,137 @@
 //! # browsercookie-rs
 //!
 //! Browsercookie-rs crate allows you to gather cookies from browsers
-//! on the system and return them in a CookieJar, so that it can be
-//! used with other http libraries like Hyper etc..
+//! on the system and return them as BrowserCookies, which convert into
+//! a CookieJar, so that it can be used with other http libraries like Hyper etc..
 //!
 //! ```rust,ignore
 //! use Browsercookie::{Browser, Attribute, CookieFinder};
 //!
-//! let mut cookie_jar = CookieFinder::builder()
+//! let cookies = CookieFinder::builder()
 //!     .with_regexp(Regex::new(".*").unwrap(), Attribute::Domain)
 //!     .with_browser(Browser::Firefox)
-//!     .build().find().await.unwrap();
+//!     .build().find().await?;
 //!
-//! println!("{}", cookie_jar.get("searched_cookie_name").unwrap());
+//! println!("{}", cookies.get("searched_cookie_name").unwrap());
+//!
+//! // Same named cookies of other domains are kept, unless collapsed into a CookieJar
+//! let cookie_jar = CookieJar::from(cookies);
 //!
 //! ```
 //!
-//! Using above `to_header` returns a string to be used with http clients as a header
-//! directlytrue.
+//! Using `header_for_url` returns the value of the `Cookie` header a browser
+//! would send to that URL, to be used with http clients directly. With the
+//! `http` feature, `BrowserCookies::to_header_value` gives it as a `HeaderValue`.
 //!
 //! ```rust,ignore
 //! use reqwest::header;
-//! use Browsercookie::{Browser, Browsercookies};
+//! use browsercookie::{Browser, CookieFinder};
 //!
-//! let mut bc = Browsercookies::new();
-//! let domain_regex = Regex::new("www.rust-lang.org");
-//! bc.from_browser(Browser::Firefox, &domain_regex).expect("Failed to get firefox browser cookies");
+//! let url = Url::parse("https://www.rust-lang.org")?;
+//! let cookie_header = CookieFinder::builder()
+//!     .with_browser(Browser::Firefox)
+//!     .build()
+//!     .header_for_url(&url)
+//!     .await?;
 //!
-//! if let Ok(cookie_header) = bc.to_header(&domain_regex) as Result<String, Box<Error>> {
-//!     let mut headers = header::HeaderMap::new();
-//!     headers.insert(header::COOKIE, header::HeaderValue::from_str(&cookie_header));
+//! let mut headers = header::HeaderMap::new();
+//! headers.insert(header::COOKIE, header::HeaderValue::from_str(&cookie_header)?);
 //!
-//!     let client = reqwest::Client::builder()
-//!         .default_headers(headers)
-//!         .build()?;
-//!     let res = client.get("https://www.rust-lang.org").send()?;
-//! }
+//! let client = reqwest::Client::builder()
+//!     .default_headers(headers)
+//!     .build()?;
+//! let res = client.get(url).send().await?;
 //! ```
-use cookie::CookieJar;
+use errors::BrowsercookieError;
+use keyring::KeyProvider;
 use regex::Regex;
-use std::{collections::HashSet, path::Path};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use strum::IntoEnumIterator;
-use strum_macros::EnumIter;
+use strum_macros::{Display, EnumIter, EnumString};
+use url::Url;
 
 #[macro_use]
 extern crate serde;
 
+mod chromium;
+mod cookies;
 pub mod errors;
 mod firefox;
+pub mod json;
+pub mod keyring;
+pub mod netscape;
+mod snapshot;
+#[cfg(feature = "reqwest")]
+mod store;
+
+pub use cookies::{BrowserCookie, BrowserCookies, MergePolicy, PartitionKey};
+#[cfg(feature = "reqwest")]
+pub use store::BrowserCookieStore;
 
 /// All supported browsers
-#[derive(PartialEq, Eq, Hash, EnumIter)]
+///
+/// Every variant but `Firefox` is Chromium based and read the same way, from
+/// its own config directory and with its own Safe Storage keyring entry.
+/// Opera has no profiles, its config directory is its single `Default` one.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EnumIter, Display, EnumString,
+)]
+#[strum(serialize_all = "kebab-case")]
 pub enum Browser {
     Firefox,
+    Chrome,
+    ChromeBeta,
+    ChromeDev,
+    ChromeCanary,
+    Chromium,
+    Brave,
+    Edge,
+    Vivaldi,
+    Opera,
+}
+
+/// A browser profile found on the system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// Name shown by the browser, e.g. "Work"
+    pub name: String,
+    /// Directory holding the profile's data
+    pub path: PathBuf,
+    /// Whether the browser opens this profile by default
+    pub is_default: bool,
+    /// Hash of the Firefox installation using this profile as its default
+    pub install_hash: Option<String>,
+}
+
+impl Profile {
+    // A profile can be picked by its display name or its directory name
+    fn is_selected(&self, selection: &ProfileSelection) -> bool {
+        match selection {
+            ProfileSelection::Default => self.is_default,
+            ProfileSelection::All => true,
+            ProfileSelection::Named(names) => {
+                let directory = self.path.file_name().and_then(|d| d.to_str());
+                names
+                    .iter()
+                    .any(|n| *n == self.name || Some(n.as_str()) == directory)
+            }
+        }
+    }
 }
 
+/// Which profiles of each browser cookies are read from
+#[derive(Default)]
+enum ProfileSelection {
+    #[default]
+    Default,
+    Named(Vec<String>),
+    All,
+}
+
+/// Cookie attribute a regex is matched against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Attribute {
     Name,
     Value,
@@ -62,11 +139,36 @@ pub enum Attribute {
     Path,
 }
 
+impl Attribute {
+    fn is_match(&self, regex: &Regex, cookie: &BrowserCookie) -> bool {
+        let value = match self {
+            Attribute::Name => Some(cookie.name()),
+            Attribute::Value => Some(cookie.value()),
+            Attribute::Domain => cookie.domain(),
+            Attribute::Path => cookie.path(),
+        };
+        value.is_some_and(|v| regex.is_match(v))
+    }
+}
+
 #[derive(Default)]
 pub struct CookieFinder<'a> {
-    regex_and_attribute_pairs: Vec<(Regex, Attribute)>,
-    browsers: HashSet<Browser>,
+    // A cookie has to match every group, and a group matches if any of its pairs does
+    regex_and_attribute_groups: Vec<Vec<(Regex, Attribute)>>,
+    containers: Vec<String>,
+    top_level_site: Option<Url>,
+    // Ordered, so ties between sources and the first error don't vary between runs
+    browsers: BTreeSet<Browser>,
     master_path: Option<&'a Path>,
+    browser_roots: HashMap<Browser, &'a Path>,
+    profile_dirs: Vec<(Browser, &'a Path)>,
+    netscape_files: Vec<&'a Path>,
+    profile_selection: ProfileSelection,
+    key_providers: Vec<Arc<dyn KeyProvider>>,
+    snapshot: bool,
+    merge_policy: MergePolicy,
+    // Set when no browser was asked for, so the ones not installed are skipped
+    all_browsers: bool,
 }
 #[derive(Default)]
 pub struct CookieFinderBuilder<'a> {
@@ -74,10 +176,37 @@ pub struct CookieFinderBuilder<'a> {
 }
 
 impl<'a> CookieFinderBuilder<'a> {
+    /// Only finds cookies whose `attribute` matches `regex`. When given several
+    /// times, cookies have to match all of them.
     pub fn with_regexp(mut self, regex: Regex, attribute: Attribute) -> Self {
         self.cookie_finder
-            .regex_and_attribute_pairs
-            .push((regex, attribute));
+            .regex_and_attribute_groups
+            .push(vec![(regex, attribute)]);
+        self
+    }
+
+    /// Only finds cookies matching at least one of the pairs. Combines with
+    /// the other regexps like a single `with_regexp` does.
+    pub fn with_any_regexp(mut self, regex_and_attribute_pairs: Vec<(Regex, Attribute)>) -> Self {
+        self.cookie_finder
+            .regex_and_attribute_groups
+            .push(regex_and_attribute_pairs);
+        self
+    }
+
+    /// Only finds cookies of the Firefox container with this name, e.g.
+    /// "Work". Can be given several times, cookies outside of containers and
+    /// those of other browsers are left out.
+    pub fn with_container(mut self, name: &str) -> Self {
+        self.cookie_finder.containers.push(String::from(name));
+        self
+    }
+
+    /// Has `find_for_url` and `header_for_url` pick cookies for a request made
+    /// by a frame of the page at `top_level`, instead of a page of the
+    /// requested site itself. Partitioned cookies depend on it.
+    pub fn with_top_level_site(mut self, top_level: Url) -> Self {
+        self.cookie_finder.top_level_site = Some(top_level);
         self
     }
 
@@ -86,18 +215,87 @@ impl<'a> CookieFinderBuilder<'a> {
         self
     }
 
+    /// Reads Firefox profiles from this `profiles.ini` instead of the one in
+    /// the user's home directory
     pub fn with_master_path(mut self, master_path: &'a Path) -> Self {
-        let _ =self.cookie_finder.master_path.insert(master_path);
+        let _ = self.cookie_finder.master_path.insert(master_path);
         self
     }
 
-    pub fn build(mut self) -> CookieFinder<'a> {
-        if self.cookie_finder.regex_and_attribute_pairs.is_empty() {
-            self.cookie_finder
-                .regex_and_attribute_pairs
-                .push((Regex::new(".*").unwrap(), Attribute::Name));
+    /// Looks for a browser's profiles under `root` instead of its default
+    /// location. That is the directory holding `profiles.ini` for Firefox and
+    /// the user data directory (holding `Local State`) for Chromium browsers.
+    pub fn with_browser_root(mut self, browser: Browser, root: &'a Path) -> Self {
+        self.cookie_finder.browser_roots.insert(browser, root);
+        self
+    }
+
+    /// Reads cookies of `browser` straight from a profile directory, skipping
+    /// profile discovery. Implies `with_browser(browser)`, can be given several times.
+    pub fn with_profile_dir(mut self, browser: Browser, profile_dir: &'a Path) -> Self {
+        self.cookie_finder.browsers.insert(browser);
+        self.cookie_finder.profile_dirs.push((browser, profile_dir));
+        self
+    }
+
+    /// Also reads cookies from a Netscape `cookies.txt` file. When no browser
+    /// is given, only the files are read. Can be given several times.
+    pub fn with_netscape_file(mut self, path: &'a Path) -> Self {
+        self.cookie_finder.netscape_files.push(path);
+        self
+    }
+
+    /// Reads the profile with this display or directory name instead of the
+    /// default one. Can be given several times.
+    pub fn with_profile(mut self, name: &str) -> Self {
+        match &mut self.cookie_finder.profile_selection {
+            ProfileSelection::Named(names) => names.push(String::from(name)),
+            ProfileSelection::Default => {
+                self.cookie_finder.profile_selection =
+                    ProfileSelection::Named(vec![String::from(name)])
+            }
+            ProfileSelection::All => (),
         }
-        if self.cookie_finder.browsers.is_empty() {
+        self
+    }
+
+    /// Reads every profile of each browser instead of only the default one
+    pub fn with_all_profiles(mut self) -> Self {
+        self.cookie_finder.profile_selection = ProfileSelection::All;
+        self
+    }
+
+    /// Adds a source for the Chromium Safe Storage password, used to decrypt
+    /// `v11` cookies. Providers are tried in the order they were added.
+    pub fn with_key_provider(mut self, key_provider: impl KeyProvider + 'static) -> Self {
+        self.cookie_finder
+            .key_providers
+            .push(Arc::new(key_provider));
+        self
+    }
+
+    /// Reads cookie databases from a private copy, including the changes the
+    /// running browser still holds in their write-ahead log (`-wal` file)
+    ///
+    /// By default databases are read as they are on disk, which misses the
+    /// latest cookies, often a fresh login session, until the browser
+    /// checkpoints or closes. The browser's files are never locked nor written.
+    pub fn with_snapshot(mut self) -> Self {
+        self.cookie_finder.snapshot = true;
+        self
+    }
+
+    /// Picks which cookie is kept when several files or browsers have the same
+    /// one, the most recently used by default. Profiles of one browser each
+    /// keep their own cookies.
+    pub fn with_merge_policy(mut self, merge_policy: MergePolicy) -> Self {
+        self.cookie_finder.merge_policy = merge_policy;
+        self
+    }
+
+    pub fn build(mut self) -> CookieFinder<'a> {
+        if self.cookie_finder.browsers.is_empty() && self.cookie_finder.netscape_files.is_empty() {
+            self.cookie_finder.all_browsers = true;
             for browser in Browser::iter() {
                 self.cookie_finder.browsers.insert(browser);
             }
@@ -111,20 +309,155 @@ impl<'a> CookieFinder<'a> {
         CookieFinderBuilder::default()
     }
 
-    pub async fn find(&self) -> CookieJar {
-        let mut cookie_jar = CookieJar::new();
-        for regex_and_attribute in &self.regex_and_attribute_pairs {
-            for browser in &self.browsers {
-                match browser {
-                    Browser::Firefox => {
-                        firefox::load(&mut cookie_jar, regex_and_attribute, None)
-                            .await
-                            .expect("Something went wrong loading the cookies from Firefox");
-                    }
-                }
+    /// Lists the profiles of an installed browser
+    pub fn profiles(&self, browser: &Browser) -> Result<Vec<Profile>, BrowsercookieError> {
+        let root = self.browser_roots.get(browser).copied();
+        match browser {
+            Browser::Firefox => {
+                let master_path = match self.master_path {
+                    Some(path) => Some(PathBuf::from(path)),
+                    None => root.map(|r| r.join("profiles.ini")),
+                };
+                firefox::profiles(master_path.as_deref())
             }
+            _ => chromium::profiles(browser, root),
+        }
+    }
+
+    fn selected_profiles(&self, browser: &Browser) -> Result<Vec<Profile>, BrowsercookieError> {
+        let profile_dirs: Vec<Profile> = self
+            .profile_dirs
+            .iter()
+            .filter(|(b, _)| b == browser)
+            .map(|(_, dir)| Profile {
+                name: dir
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                path: PathBuf::from(dir),
+                is_default: false,
+                install_hash: None,
+            })
+            .collect();
+        if !profile_dirs.is_empty() {
+            return Ok(profile_dirs);
         }
-        cookie_jar
+
+        let profiles: Vec<Profile> = self
+            .profiles(browser)?
+            .into_iter()
+            .filter(|p| p.is_selected(&self.profile_selection))
+            .collect();
+        if profiles.is_empty() {
+            return Err(BrowsercookieError::ProfileMissing {
+                browser: *browser,
+                path: None,
+            });
+        }
+        Ok(profiles)
+    }
+
+    fn is_match(&self, cookie: &BrowserCookie) -> bool {
+        let in_container = self.containers.is_empty()
+            || cookie
+                .container()
+                .is_some_and(|c| self.containers.iter().any(|n| n == c));
+        in_container
+            && self.regex_and_attribute_groups.iter().all(|group| {
+                group
+                    .iter()
+                    .any(|(regex, attribute)| attribute.is_match(regex, cookie))
+            })
+    }
+
+    async fn load(
+        &self,
+        cookies: &mut BrowserCookies,
+        browser: &Browser,
+        errors: &mut Vec<BrowsercookieError>,
+    ) {
+        let profiles = match self.selected_profiles(browser) {
+            Ok(profiles) => profiles,
+            // Browsers that weren't asked for explicitly may just not be installed
+            Err(BrowsercookieError::ProfileMissing { .. }) if self.all_browsers => return,
+            Err(error) => {
+                errors.push(error);
+                return;
+            }
+        };
+        if profiles.len() > 1 {
+            cookies.separate_profiles.insert(*browser);
+        }
+        match browser {
+            Browser::Firefox => firefox::load(cookies, &profiles, self.snapshot, errors).await,
+            _ => {
+                chromium::load(
+                    cookies,
+                    browser,
+                    &profiles,
+                    &self.key_providers,
+                    self.snapshot,
+                    errors,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Reads the cookies of every selected browser, failing on the first
+    /// browser, profile or file that can't be read
+    ///
+    /// When no browser or cookie file was asked for, every browser is read as
+    /// far as it can be instead: one that isn't installed, whose cookies can't
+    /// be decrypted or whose profiles are broken doesn't fail the others. Use
+    /// [`find_with_errors`](Self::find_with_errors) to learn what was skipped.
+    pub async fn find(&self) -> Result<BrowserCookies, BrowsercookieError> {
+        let (cookies, errors) = self.find_with_errors().await;
+        match errors.into_iter().next() {
+            Some(_) if self.all_browsers => Ok(cookies),
+            Some(error) => Err(error),
+            None => Ok(cookies),
+        }
+    }
+
+    /// Reads the cookies a browser would send along with a request to `url`
+    ///
+    /// Cookies are selected with the RFC 6265 rules on top of the regexps, and
+    /// come in the order a browser would send them.
+    pub async fn find_for_url(&self, url: &Url) -> Result<BrowserCookies, BrowsercookieError> {
+        let mut cookies = self.find().await?;
+        cookies.retain_for_url_under(url, self.top_level_site.as_ref().unwrap_or(url));
+        Ok(cookies)
+    }
+
+    /// Returns the value of the `Cookie` header a browser would send to `url`
+    pub async fn header_for_url(&self, url: &Url) -> Result<String, BrowsercookieError> {
+        Ok(self
+            .find()
+            .await?
+            .header_for_url_under(url, self.top_level_site.as_ref().unwrap_or(url)))
+    }
+
+    /// Reads the cookies of every selected browser, skipping the sources that
+    /// can't be read
+    ///
+    /// Browsers are read in the order of [`Browser`], then cookie files in the
+    /// order they were added.
+    ///
+    /// Returns the cookies that were found, along with an error for each
+    /// browser, profile or file that was skipped.
+    pub async fn find_with_errors(&self) -> (BrowserCookies, Vec<BrowsercookieError>) {
+        let mut cookies = BrowserCookies::new();
+        cookies.merge_policy = self.merge_policy;
+        let mut errors = vec![];
+        for browser in &self.browsers {
+            self.load(&mut cookies, browser, &mut errors).await;
+        }
+        for path in &self.netscape_files {
+            netscape::load(&mut cookies, path, &mut errors);
+        }
+        cookies.retain(|c| self.is_match(c));
+        (cookies, errors)
     }
 }
 
@@ -140,7 +473,8 @@ mod tests {
             .with_browser(Browser::Firefox)
             .build()
             .find()
-            .await;
+            .await
+            .unwrap();
         assert_eq!(cookies.iter().count(), 2);
         let recovery_cookie = cookies.get("name").unwrap();
         assert_eq!(recovery_cookie.value(), "value");
@@ -156,14 +490,17 @@ mod tests {
 
     #[tokio::test]
     async fn test_would_find_all_cookies_with_no_builder_withs() {
-        let cookies = CookieFinder::builder().build().find().await;
-        assert_eq!(cookies.iter().count(), 3);
+        let cookies = CookieFinder::builder().build().find().await.unwrap();
+        assert_eq!(cookies.iter().count(), 7);
         let recovery_cookie = cookies.get("name").unwrap();
         assert_eq!(recovery_cookie.value(), "value");
         assert_eq!(recovery_cookie.domain(), Some("httpbin.org"));
         assert_eq!(recovery_cookie.path(), Some("/"));
 
-        let sqlite_cookie = cookies.get("somename").unwrap();
+        let sqlite_cookie = cookies
+            .get_for_domain("somehost")
+            .find(|c| c.name() == "somename")
+            .unwrap();
 
         assert_eq!(sqlite_cookie.value(), "somevalue");
         assert_eq!(sqlite_cookie.path(), Some("/"));
@@ -174,5 +511,561 @@ mod tests {
         assert_eq!(other_sqlite_cookie.value(), "othervalue");
         assert_eq!(other_sqlite_cookie.path(), Some("/"));
         assert_eq!(other_sqlite_cookie.domain(), Some("otherhost"));
+
+        let chrome_cookie = cookies.get("chromename").unwrap();
+
+        assert_eq!(chrome_cookie.value(), "chromevalue");
+        assert_eq!(chrome_cookie.domain(), Some("chromehost.example"));
+
+        let brave_cookie = cookies.get("bravename").unwrap();
+
+        assert_eq!(brave_cookie.value(), "bravevalue");
+        assert_eq!(brave_cookie.domain(), Some("bravehost.example"));
+
+        // Same named cookies of different domains are all kept
+        let mut same_named: Vec<&str> = cookies.get_all("somename").map(|c| c.value()).collect();
+        same_named.sort();
+        assert_eq!(same_named, vec!["bravesomevalue", "somevalue"]);
+    }
+
+    #[tokio::test]
+    async fn test_chrome() {
+        let domain_regex = Regex::new(r"chromehost").unwrap();
+        let cookies = CookieFinder::builder()
+            .with_regexp(domain_regex, Attribute::Domain)
+            .with_browser(Browser::Chrome)
+            .with_key_provider(keyring::StaticPassword::new("testpassword"))
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 1);
+        let cookie = cookies.get("chromename").unwrap();
+        assert_eq!(cookie.value(), "chromevalue");
+        assert_eq!(cookie.domain(), Some("chromehost.example"));
+    }
+
+    #[tokio::test]
+    async fn test_chrome_with_key_provider() {
+        let domain_regex = Regex::new(r"keyringhost").unwrap();
+        let cookies = CookieFinder::builder()
+            .with_regexp(domain_regex, Attribute::Domain)
+            .with_browser(Browser::Chrome)
+            .with_key_provider(keyring::StaticPassword::new("wrongpassword"))
+            .with_key_provider(keyring::StaticPassword::new("testpassword"))
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 1);
+        assert_eq!(cookies.get("keyringname").unwrap().value(), "keyringvalue");
+    }
+
+    #[tokio::test]
+    async fn test_chrome_with_profile() {
+        let cookies = CookieFinder::builder()
+            .with_browser(Browser::Chrome)
+            .with_profile("Work")
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 1);
+        assert_eq!(cookies.get("workname").unwrap().value(), "workvalue");
+
+        let cookies = CookieFinder::builder()
+            .with_browser(Browser::Chrome)
+            .with_profile("Default")
+            .with_profile("Profile 1")
+            .with_key_provider(keyring::StaticPassword::new("testpassword"))
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_firefox_with_profile() {
+        let cookies = CookieFinder::builder()
+            .with_browser(Browser::Firefox)
+            .with_profile("work")
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 1);
+        assert_eq!(cookies.get("workname").unwrap().value(), "workvalue");
+    }
+
+    #[tokio::test]
+    async fn test_with_all_profiles() {
+        let cookies = CookieFinder::builder()
+            .with_browser(Browser::Firefox)
+            .with_all_profiles()
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 4);
+        assert_eq!(cookies.get("somename").unwrap().value(), "somevalue");
+        assert_eq!(cookies.get("workname").unwrap().value(), "workvalue");
+    }
+
+    #[tokio::test]
+    async fn test_with_master_path() {
+        let mut master_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        master_path.push("tests/resources/profiles_work.ini");
+        let cookies = CookieFinder::builder()
+            .with_browser(Browser::Firefox)
+            .with_master_path(&master_path)
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 1);
+        assert_eq!(cookies.get("workname").unwrap().value(), "workvalue");
+    }
+
+    #[tokio::test]
+    async fn test_with_browser_root() {
+        let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        root.push("tests/resources/BraveSoftware/Brave-Browser");
+        let cookies = CookieFinder::builder()
+            .with_browser(Browser::Chrome)
+            .with_browser_root(Browser::Chrome, &root)
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 2);
+        assert_eq!(cookies.get("bravename").unwrap().value(), "bravevalue");
+    }
+
+    #[tokio::test]
+    async fn test_with_profile_dir() {
+        let mut firefox_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        firefox_dir.push("tests/resources/Profiles/x7kq2m4c.work");
+        let mut chrome_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        chrome_dir.push("tests/resources/BraveSoftware/Brave-Browser/Default");
+        let cookies = CookieFinder::builder()
+            .with_profile_dir(Browser::Firefox, &firefox_dir)
+            .with_profile_dir(Browser::Chrome, &chrome_dir)
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 3);
+        assert_eq!(cookies.get("workname").unwrap().value(), "workvalue");
+        assert_eq!(cookies.get("bravename").unwrap().value(), "bravevalue");
+    }
+
+    #[tokio::test]
+    async fn test_regexp_attributes() {
+        let name_regex = Regex::new(r"^somename$").unwrap();
+        let cookies = CookieFinder::builder()
+            .with_regexp(name_regex, Attribute::Name)
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 2);
+        assert!(cookies.iter().all(|c| c.name() == "somename"));
+
+        let value_regex = Regex::new(r"^chromevalue$").unwrap();
+        let cookies = CookieFinder::builder()
+            .with_regexp(value_regex, Attribute::Value)
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 1);
+        assert_eq!(cookies.get("chromename").unwrap().value(), "chromevalue");
+
+        let path_regex = Regex::new(r"^/plain").unwrap();
+        let cookies = CookieFinder::builder()
+            .with_regexp(path_regex, Attribute::Path)
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 1);
+        assert_eq!(cookies.get("plainname").unwrap().path(), Some("/plain"));
+    }
+
+    #[tokio::test]
+    async fn test_regexps_combine() {
+        let cookies = CookieFinder::builder()
+            .with_regexp(Regex::new(r"^somename$").unwrap(), Attribute::Name)
+            .with_regexp(Regex::new(r"^somehost$").unwrap(), Attribute::Domain)
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 1);
+        assert_eq!(cookies.get("somename").unwrap().value(), "somevalue");
+
+        let cookies = CookieFinder::builder()
+            .with_any_regexp(vec![
+                (Regex::new(r"^bravename$").unwrap(), Attribute::Name),
+                (Regex::new(r"^otherhost$").unwrap(), Attribute::Domain),
+            ])
+            .with_regexp(Regex::new(r"value$").unwrap(), Attribute::Value)
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.iter().count(), 2);
+        assert!(cookies.get("bravename").is_some());
+        assert!(cookies.get("othername").is_some());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let finder = CookieFinder::builder().build();
+
+        let firefox_profiles = finder.profiles(&Browser::Firefox).unwrap();
+        assert_eq!(firefox_profiles.len(), 2);
+        assert_eq!(firefox_profiles[1].name, "work");
+
+        let chrome_profiles = finder.profiles(&Browser::Chrome).unwrap();
+        assert_eq!(chrome_profiles.len(), 2);
+        assert_eq!(chrome_profiles[1].name, "Work");
+
+        assert!(matches!(
+            finder.profiles(&Browser::Opera),
+            Err(BrowsercookieError::ProfileMissing { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_find_fails_for_missing_browser() {
+        let result = CookieFinder::builder()
+            .with_browser(Browser::Opera)
+            .build()
+            .find()
+            .await;
+
+        let error = result.unwrap_err();
+        assert_eq!(error.browser(), Some(Browser::Opera));
+        assert!(matches!(error, BrowsercookieError::ProfileMissing { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_find_with_errors() {
+        let mut corrupt_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        corrupt_dir.push("tests/resources/Profiles/brokenxx.corrupt");
+        let mut work_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        work_dir.push("tests/resources/Profiles/x7kq2m4c.work");
+
+        let finder = CookieFinder::builder()
+            .with_profile_dir(Browser::Firefox, &corrupt_dir)
+            .with_profile_dir(Browser::Firefox, &work_dir)
+            .build();
+
+        assert!(finder.find().await.is_err());
+
+        let (cookies, errors) = finder.find_with_errors().await;
+        assert_eq!(cookies.iter().count(), 1);
+        assert_eq!(cookies.get("workname").unwrap().value(), "workvalue");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].browser(), Some(Browser::Firefox));
+        assert!(errors[0]
+            .path()
+            .unwrap()
+            .ends_with("brokenxx.corrupt/cookies.sqlite"));
+    }
+
+    #[tokio::test]
+    async fn test_find_for_url() {
+        let finder = CookieFinder::builder()
+            .with_browser(Browser::Chrome)
+            .with_key_provider(keyring::StaticPassword::new("testpassword"))
+            .build();
+
+        let cookies = finder
+            .find_for_url(&Url::parse("https://www.chromehost.example/").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies.get("chromename").unwrap().value(), "chromevalue");
+
+        // chromename is Secure
+        let cookies = finder
+            .find_for_url(&Url::parse("http://www.chromehost.example/").unwrap())
+            .await
+            .unwrap();
+        assert!(cookies.is_empty());
+
+        // plainname is host-only, with path /plain
+        let cookies = finder
+            .find_for_url(&Url::parse("http://plainhost.example/plain/page").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(cookies.len(), 1);
+        assert!(cookies.get("plainname").unwrap().is_host_only());
+        let cookies = finder
+            .find_for_url(&Url::parse("http://www.plainhost.example/plain").unwrap())
+            .await
+            .unwrap();
+        assert!(cookies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_header_for_url() {
+        let header = CookieFinder::builder()
+            .with_browser(Browser::Firefox)
+            .with_browser(Browser::Brave)
+            .build()
+            .header_for_url(&Url::parse("https://bravehost.example/").unwrap())
+            .await
+            .unwrap();
+
+        let mut cookies: Vec<&str> = header.split("; ").collect();
+        cookies.sort_unstable();
+        assert_eq!(cookies, ["bravename=bravevalue", "somename=bravesomevalue"]);
+    }
+
+    #[tokio::test]
+    async fn test_with_netscape_file() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/cookies.txt");
+
+        let cookies = CookieFinder::builder()
+            .with_netscape_file(&path)
+            .with_regexp(Regex::new(r"^filename$").unwrap(), Attribute::Name)
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies.get("filename").unwrap().value(), "filevalue");
+
+        let cookies = CookieFinder::builder()
+            .with_netscape_file(&path)
+            .with_browser(Browser::Brave)
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_with_top_level_site() {
+        let mut profile_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        profile_dir.push("tests/resources/Profiles/p4rt1t10n.partitioned");
+        let widget = Url::parse("https://widget.example/").unwrap();
+
+        let header = CookieFinder::builder()
+            .with_profile_dir(Browser::Firefox, &profile_dir)
+            .build()
+            .header_for_url(&widget)
+            .await
+            .unwrap();
+        assert_eq!(header, "");
+
+        let cookies = CookieFinder::builder()
+            .with_profile_dir(Browser::Firefox, &profile_dir)
+            .with_top_level_site(Url::parse("https://shop.example/cart").unwrap())
+            .build()
+            .find_for_url(&widget)
+            .await
+            .unwrap();
+        assert_eq!(cookies.len(), 2);
+        assert!(cookies
+            .iter()
+            .all(|c| c.partition_key().unwrap().site == "shop.example"));
+    }
+
+    #[tokio::test]
+    async fn test_with_snapshot() {
+        let cookies = CookieFinder::builder()
+            .with_browser(Browser::Firefox)
+            .with_browser(Browser::Chrome)
+            .with_key_provider(keyring::StaticPassword::new("testpassword"))
+            .with_snapshot()
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert!(cookies.get("somename").is_some());
+        assert_eq!(cookies.get("chromename").unwrap().value(), "chromevalue");
+    }
+
+    #[tokio::test]
+    async fn test_with_merge_policy() {
+        // A session copy of the sqlite cookie somename of the default Firefox profile
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cookies.txt");
+        std::fs::write(&path, "somehost\tFALSE\t/\tTRUE\t0\tsomename\tfilevalue\n").unwrap();
+        let finder = |merge_policy| {
+            CookieFinder::builder()
+                .with_browser(Browser::Firefox)
+                .with_netscape_file(&path)
+                .with_regexp(Regex::new(r"^somename$").unwrap(), Attribute::Name)
+                .with_merge_policy(merge_policy)
+                .build()
+        };
+
+        let cookies = finder(MergePolicy::NewestWins).find().await.unwrap();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies.get("somename").unwrap().value(), "somevalue");
+        assert_eq!(
+            cookies.get("somename").unwrap().browser(),
+            Some(Browser::Firefox)
+        );
+
+        let cookies = finder(MergePolicy::PreferSession).find().await.unwrap();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies.get("somename").unwrap().value(), "filevalue");
+        assert_eq!(cookies.get("somename").unwrap().browser(), None);
+
+        let cookies = finder(MergePolicy::KeepAll).find().await.unwrap();
+        assert_eq!(cookies.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unrequested_browser_errors_are_skipped() {
+        let edge_root = tempfile::tempdir().unwrap();
+        std::fs::write(edge_root.path().join("Local State"), "not json").unwrap();
+        let finder = CookieFinder::builder()
+            .with_browser_root(Browser::Edge, edge_root.path())
+            .build();
+
+        let (cookies, errors) = finder.find_with_errors().await;
+        assert!(errors.iter().any(|e| matches!(
+            e,
+            BrowsercookieError::Json {
+                browser: Browser::Edge,
+                ..
+            }
+        )));
+        assert_eq!(finder.find().await.unwrap().len(), cookies.len());
+
+        let finder = CookieFinder::builder()
+            .with_browser(Browser::Edge)
+            .with_browser_root(Browser::Edge, edge_root.path())
+            .build();
+        assert!(matches!(
+            finder.find().await,
+            Err(BrowsercookieError::Json { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_chrome_without_key_provider() {
+        let (cookies, errors) = CookieFinder::builder()
+            .with_browser(Browser::Chrome)
+            .build()
+            .find_with_errors()
+            .await;
+        assert_eq!(cookies.get("chromename").unwrap().value(), "chromevalue");
+        assert!(cookies.get("keyringname").is_none());
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            BrowsercookieError::Decryption {
+                browser: Browser::Chrome,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_browsers_are_read_in_order() {
+        let (_, errors) = CookieFinder::builder()
+            .with_browser(Browser::Edge)
+            .with_browser_root(Browser::Edge, Path::new("/nonexistent/edge"))
+            .with_browser(Browser::Chrome)
+            .with_browser_root(Browser::Chrome, Path::new("/nonexistent/chrome"))
+            .with_browser(Browser::Firefox)
+            .with_browser_root(Browser::Firefox, Path::new("/nonexistent/firefox"))
+            .build()
+            .find_with_errors()
+            .await;
+        let browsers: Vec<Option<Browser>> = errors.iter().map(|e| e.browser()).collect();
+        assert_eq!(
+            browsers,
+            [
+                Some(Browser::Firefox),
+                Some(Browser::Chrome),
+                Some(Browser::Edge)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_with_all_profiles_keeps_each_account() {
+        // Two Firefox profiles logged in to the same site
+        let master_dir = tempfile::tempdir().unwrap();
+        let mut sqlite_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sqlite_path.push("tests/resources/Profiles/x7kq2m4c.work/cookies.sqlite");
+        for profile in ["first", "second"] {
+            std::fs::create_dir(master_dir.path().join(profile)).unwrap();
+            std::fs::copy(
+                &sqlite_path,
+                master_dir.path().join(profile).join("cookies.sqlite"),
+            )
+            .unwrap();
+        }
+        let master_path = master_dir.path().join("profiles.ini");
+        std::fs::write(
+            &master_path,
+            "[Profile0]\nName=first\nIsRelative=1\nPath=first\nDefault=1\n\n\
+             [Profile1]\nName=second\nIsRelative=1\nPath=second\n",
+        )
+        .unwrap();
+
+        let cookies = CookieFinder::builder()
+            .with_browser(Browser::Firefox)
+            .with_master_path(&master_path)
+            .with_all_profiles()
+            .build()
+            .find()
+            .await
+            .unwrap();
+        let mut profiles: Vec<Option<&str>> =
+            cookies.get_all("workname").map(|c| c.profile()).collect();
+        profiles.sort();
+        assert_eq!(profiles, [Some("first"), Some("second")]);
+
+        let cookies = CookieFinder::builder()
+            .with_browser(Browser::Firefox)
+            .with_master_path(&master_path)
+            .with_profile("second")
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.get_all("workname").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_with_container() {
+        let mut profile_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        profile_dir.push("tests/resources/Profiles/c0nt41nr.containers");
+
+        let cookies = CookieFinder::builder()
+            .with_profile_dir(Browser::Firefox, &profile_dir)
+            .with_container("Customer A")
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.len(), 1);
+        let cookie = cookies.get("tenant").unwrap();
+        assert_eq!(cookie.value(), "customer-a");
+        assert_eq!(cookie.container(), Some("Customer A"));
+
+        let cookies = CookieFinder::builder()
+            .with_profile_dir(Browser::Firefox, &profile_dir)
+            .with_container("Customer B")
+            .with_container("Work")
+            .build()
+            .find()
+            .await
+            .unwrap();
+        assert_eq!(cookies.len(), 2);
     }
 }
@@ -0,0 +1,321 @@
+//! Netscape `cookies.txt` files, as read by curl, wget, yt-dlp and aria2
+//!
+//! Every cookie is a line of tab separated fields: domain, whether subdomains
+//! are included, path, secure, expiry (Unix time, 0 for session cookies), name
+//! and value. HttpOnly cookies have their domain prefixed with `#HttpOnly_`,
+//! other lines starting with `#` are comments.
+//!
+//! ```rust,ignore
+//! let cookies = CookieFinder::builder().build().find().await?;
+//! let mut file = File::create("cookies.txt")?;
+//! browsercookie::netscape::write(&cookies, &mut file)?;
+//!
+//! // Files are also read as a source of the finder, along with browsers
+//! let cookies = CookieFinder::builder()
+//!     .with_netscape_file(Path::new("cookies.txt"))
+//!     .build()
+//!     .find()
+//!     .await?;
+//! ```
+use cookie::time::OffsetDateTime;
+use cookie::Cookie;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cookies::{BrowserCookie, BrowserCookies};
+use crate::errors::BrowsercookieError;
+
+const HEADER: &str = "# Netscape HTTP Cookie File\n\
+                      # https://curl.se/docs/http-cookies.html\n\
+                      # This file was generated by browsercookie-rs! Edit at your own risk.\n\n";
+
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+fn bool_field(value: bool) -> &'static str {
+    if value {
+        "TRUE"
+    } else {
+        "FALSE"
+    }
+}
+
+fn parse_bool(field: &str) -> Option<bool> {
+    if field.eq_ignore_ascii_case("TRUE") {
+        Some(true)
+    } else if field.eq_ignore_ascii_case("FALSE") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn parse_line(line: &str) -> Result<Option<BrowserCookie>, String> {
+    let line = line.trim_end_matches('\r');
+    let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+        Some(line) => (line, true),
+        None => (line, false),
+    };
+    if !http_only && (line.starts_with('#') || line.trim().is_empty()) {
+        return Ok(None);
+    }
+
+    // Some exporters leave out the tab before an empty value
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 7 && fields.len() != 6 {
+        return Err(format!(
+            "expected 7 tab separated fields, found {}",
+            fields.len()
+        ));
+    }
+    let domain = fields[0];
+    if domain.is_empty() {
+        return Err(String::from("domain is empty"));
+    }
+    let include_subdomains = parse_bool(fields[1]).ok_or_else(|| {
+        format!(
+            "include subdomains flag {:?} isn't TRUE or FALSE",
+            fields[1]
+        )
+    })?;
+    let secure = parse_bool(fields[3])
+        .ok_or_else(|| format!("secure flag {:?} isn't TRUE or FALSE", fields[3]))?;
+    let expiry: i64 = fields[4]
+        .parse()
+        .map_err(|_| format!("expiry {:?} isn't a number", fields[4]))?;
+
+    let mut cookie = Cookie::build((
+        String::from(fields[5]),
+        String::from(fields.get(6).copied().unwrap_or("")),
+    ))
+    .domain(String::from(domain))
+    .path(String::from(fields[2]))
+    .secure(secure)
+    .http_only(http_only);
+    // 0 marks a session cookie
+    if expiry != 0 {
+        let expires = OffsetDateTime::from_unix_timestamp(expiry)
+            .map_err(|_| format!("expiry {} is out of range", expiry))?;
+        cookie = cookie.expires(expires);
+    }
+
+    let mut cookie = BrowserCookie::new(cookie.build());
+    cookie.host_only = !include_subdomains && !domain.starts_with('.');
+    Ok(Some(cookie))
+}
+
+pub(crate) fn load(
+    cookies: &mut BrowserCookies,
+    path: &Path,
+    errors: &mut Vec<BrowsercookieError>,
+) {
+    // Lines that can't be parsed are reported in errors, the others still load
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(source) => {
+            errors.push(BrowsercookieError::Io {
+                browser: None,
+                path: PathBuf::from(path),
+                source,
+            });
+            return;
+        }
+    };
+    let mut file_cookies = BrowserCookies::new();
+    for (index, line) in content.lines().enumerate() {
+        match parse_line(line) {
+            Ok(Some(cookie)) => file_cookies.add(cookie),
+            Ok(None) => (),
+            Err(reason) => errors.push(BrowsercookieError::InvalidCookieFile {
+                path: PathBuf::from(path),
+                line: index + 1,
+                reason,
+            }),
+        }
+    }
+    cookies.add_from_source(file_cookies, None, None, path);
+}
+
+/// Reads a Netscape cookie file, failing on the first line that can't be parsed
+pub fn read(path: &Path) -> Result<BrowserCookies, BrowsercookieError> {
+    let mut cookies = BrowserCookies::new();
+    let mut errors = vec![];
+    load(&mut cookies, path, &mut errors);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(cookies),
+    }
+}
+
+fn format_line(cookie: &BrowserCookie) -> Option<String> {
+    // A tab or line break in a field would shift or split the line
+    let fields_are_clean = [cookie.name(), cookie.value(), cookie.path().unwrap_or("/")]
+        .iter()
+        .all(|f| !f.contains(['\t', '\r', '\n']));
+    if !fields_are_clean {
+        return None;
+    }
+
+    let domain = cookie.domain()?;
+    let include_subdomains = !cookie.is_host_only();
+    Some(format!(
+        "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+        if cookie.http_only() == Some(true) {
+            HTTP_ONLY_PREFIX
+        } else {
+            ""
+        },
+        if include_subdomains { "." } else { "" },
+        domain,
+        bool_field(include_subdomains),
+        cookie.path().unwrap_or("/"),
+        bool_field(cookie.secure() == Some(true)),
+        cookie
+            .expires_datetime()
+            .map_or(0, |expires| expires.unix_timestamp()),
+        cookie.name(),
+        cookie.value(),
+    ))
+}
+
+/// Writes the cookies as a Netscape cookie file
+///
+/// Cookies without a domain, or with a tab or line break in their name,
+/// value or path, can't be represented and are left out.
+pub fn write(cookies: &BrowserCookies, writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(HEADER.as_bytes())?;
+    for line in cookies.iter().filter_map(format_line) {
+        writer.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Returns the cookies as the content of a Netscape cookie file
+pub fn to_string(cookies: &BrowserCookies) -> String {
+    let mut buffer = Vec::new();
+    write(cookies, &mut buffer).expect("Writing to a Vec can't fail");
+    String::from_utf8(buffer).expect("Cookies are valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/cookies.txt");
+
+        let cookies = read(&path).unwrap();
+
+        assert_eq!(cookies.len(), 2);
+        let cookie = cookies.get("filename").unwrap();
+        assert_eq!(cookie.browser(), None);
+        assert_eq!(cookie.source_file(), Some(path.as_path()));
+        assert_eq!(cookie.value(), "filevalue");
+        assert_eq!(cookie.domain(), Some("filehost.example"));
+        assert!(!cookie.is_host_only());
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.http_only(), Some(false));
+        assert_eq!(cookie.expires(), None);
+
+        let http_only_cookie = cookies.get("filetoken").unwrap();
+        assert_eq!(http_only_cookie.value(), "filetokenvalue");
+        assert_eq!(http_only_cookie.domain(), Some("api.filehost.example"));
+        assert_eq!(http_only_cookie.path(), Some("/v1"));
+        assert!(http_only_cookie.is_host_only());
+        assert_eq!(http_only_cookie.http_only(), Some(true));
+        assert_eq!(
+            http_only_cookie.expires_datetime(),
+            OffsetDateTime::from_unix_timestamp(4_102_444_800).ok()
+        );
+    }
+
+    #[test]
+    fn test_load_reports_invalid_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cookies.txt");
+        fs::write(
+            &path,
+            "example.com\tFALSE\t/\tFALSE\tsoon\tname\tvalue\n\
+             example.com\tFALSE\t/\tFALSE\t0\tempty\n\
+             not a cookie line\n",
+        )
+        .unwrap();
+
+        let mut cookies = BrowserCookies::new();
+        let mut errors = vec![];
+        load(&mut cookies, &path, &mut errors);
+
+        assert_eq!(cookies.get("empty").unwrap().value(), "");
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            errors[0],
+            BrowsercookieError::InvalidCookieFile { line: 1, .. }
+        ));
+        assert!(matches!(
+            errors[1],
+            BrowsercookieError::InvalidCookieFile { line: 3, .. }
+        ));
+        assert!(read(&path).is_err());
+    }
+
+    #[test]
+    fn test_write_then_read() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/cookies.txt");
+        let cookies = read(&path).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let written = dir.path().join("cookies.txt");
+        fs::write(&written, to_string(&cookies)).unwrap();
+
+        let read_back = read(&written).unwrap();
+        assert_eq!(read_back.len(), cookies.len());
+        for (read_cookie, cookie) in read_back.iter().zip(cookies.iter()) {
+            assert_eq!(read_cookie.cookie(), cookie.cookie());
+            assert_eq!(read_cookie.is_host_only(), cookie.is_host_only());
+            assert_eq!(read_cookie.source_file(), Some(written.as_path()));
+        }
+    }
+
+    #[test]
+    fn test_write() {
+        let mut cookies = BrowserCookies::new();
+        let mut domain_cookie = BrowserCookie::new(
+            Cookie::build(("session", "abc"))
+                .domain("example.com")
+                .path("/")
+                .secure(true)
+                .expires(OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap())
+                .build(),
+        );
+        domain_cookie.host_only = false;
+        cookies.add(domain_cookie);
+        let mut host_only_cookie = BrowserCookie::new(
+            Cookie::build(("token", "xyz"))
+                .domain("api.example.com")
+                .path("/v1")
+                .http_only(true)
+                .build(),
+        );
+        host_only_cookie.host_only = true;
+        cookies.add(host_only_cookie);
+        cookies.add(
+            Cookie::build(("broken", "a\tb"))
+                .domain("example.com")
+                .build(),
+        );
+
+        let lines: Vec<String> = to_string(&cookies).lines().map(String::from).collect();
+
+        assert_eq!(lines[0], "# Netscape HTTP Cookie File");
+        assert_eq!(
+            &lines[4..],
+            [
+                ".example.com\tTRUE\t/\tTRUE\t1700000000\tsession\tabc",
+                "#HttpOnly_api.example.com\tFALSE\t/v1\tFALSE\t0\ttoken\txyz",
+            ]
+        );
+    }
+}
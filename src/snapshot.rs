@@ -0,0 +1,191 @@
+//! Opening cookie databases, either as they are on disk or as a private copy
+//! including the changes still held in their write-ahead log
+use sqlx::prelude::*;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::SqliteConnection;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use crate::errors::BrowsercookieError;
+use crate::Browser;
+
+// Files SQLite keeps next to a database in WAL mode
+const WAL_SUFFIXES: [&str; 2] = ["-wal", "-shm"];
+
+// Copies made while the browser writes to the database are retried
+const COPY_ATTEMPTS: usize = 3;
+
+static SNAPSHOT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// An open cookie database, and the snapshot it was opened from if any
+pub(crate) struct Database {
+    pub(crate) conn: SqliteConnection,
+    // Removed when dropped, which only waits for the connection to be closed
+    // when that was done with close()
+    _snapshot: Option<Snapshot>,
+}
+
+impl Database {
+    /// Closes the connection, then removes the snapshot
+    pub(crate) async fn close(self) {
+        // A dropped connection is closed later by its worker thread, which may
+        // still have the snapshot's files open while they are being removed
+        let _ = self.conn.close().await;
+    }
+}
+
+/// A private copy of a database with its WAL files, removed when dropped
+struct Snapshot {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Snapshot {
+    fn create_dir() -> io::Result<PathBuf> {
+        loop {
+            let dir = std::env::temp_dir().join(format!(
+                "browsercookie-{}-{}",
+                process::id(),
+                SNAPSHOT_COUNT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let mut builder = fs::DirBuilder::new();
+            #[cfg(unix)]
+            std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+            match builder.create(&dir) {
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                result => return result.map(|_| dir),
+            }
+        }
+    }
+
+    fn copy(db_path: &Path) -> io::Result<Snapshot> {
+        let dir = Snapshot::create_dir()?;
+        let snapshot = Snapshot {
+            path: dir.join(db_path.file_name().unwrap_or_default()),
+            dir,
+        };
+        for _ in 0..COPY_ATTEMPTS {
+            // Only plain reads, the browser's locks are left alone
+            let before = file_stamps(db_path);
+            for suffix in WAL_SUFFIXES {
+                let _ = fs::remove_file(with_suffix(&snapshot.path, suffix));
+            }
+            fs::copy(db_path, &snapshot.path)?;
+            for suffix in WAL_SUFFIXES {
+                match fs::copy(
+                    with_suffix(db_path, suffix),
+                    with_suffix(&snapshot.path, suffix),
+                ) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                    result => {
+                        result?;
+                    }
+                }
+            }
+            if file_stamps(db_path) == before {
+                return Ok(snapshot);
+            }
+        }
+        // A torn copy could mix pages from different transactions
+        Err(io::Error::other(format!(
+            "database kept changing during {} copy attempts",
+            COPY_ATTEMPTS
+        )))
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn file_stamps(db_path: &Path) -> Vec<Option<(u64, Option<SystemTime>)>> {
+    std::iter::once(PathBuf::from(db_path))
+        .chain(WAL_SUFFIXES.iter().map(|s| with_suffix(db_path, s)))
+        .map(|path| {
+            let metadata = fs::metadata(path).ok()?;
+            Some((metadata.len(), metadata.modified().ok()))
+        })
+        .collect()
+}
+
+/// Opens the cookie database at `db_path`
+///
+/// The database itself is opened immutable, so SQLite neither locks it nor
+/// reads its WAL, missing the latest changes of a running browser. A snapshot
+/// is a copy of the database and its WAL files, checkpointed before reading.
+pub(crate) async fn open(
+    browser: &Browser,
+    db_path: &Path,
+    snapshot: bool,
+) -> Result<Database, BrowsercookieError> {
+    let db_error = |e| BrowsercookieError::from_sqlx(*browser, db_path, e);
+    if !snapshot {
+        let options = SqliteConnectOptions::new()
+            .filename(db_path)
+            .read_only(true)
+            .immutable(true);
+        return Ok(Database {
+            conn: SqliteConnection::connect_with(&options)
+                .await
+                .map_err(db_error)?,
+            _snapshot: None,
+        });
+    }
+
+    let snapshot = Snapshot::copy(db_path).map_err(|source| BrowsercookieError::Io {
+        browser: Some(*browser),
+        path: PathBuf::from(db_path),
+        source,
+    })?;
+    let options = SqliteConnectOptions::new().filename(&snapshot.path);
+    let mut conn = SqliteConnection::connect_with(&options)
+        .await
+        .map_err(db_error)?;
+    if let Err(e) = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(&mut conn)
+        .await
+    {
+        let _ = conn.close().await;
+        return Err(db_error(e));
+    }
+    Ok(Database {
+        conn,
+        _snapshot: Some(snapshot),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshot_is_removed() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/resources/Profiles/x7kq2m4c.work/cookies.sqlite");
+
+        let mut database = open(&Browser::Firefox, &path, true).await.unwrap();
+        let count: i64 = sqlx::query("SELECT count(*) FROM moz_cookies")
+            .fetch_one(&mut database.conn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(count, 1);
+
+        let dir = database._snapshot.as_ref().unwrap().dir.clone();
+        assert!(dir.join("cookies.sqlite").exists());
+        database.close().await;
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,206 @@
+//! A reqwest cookie store seeded with browser cookies
+//!
+//! ```rust,ignore
+//! use browsercookie::{Browser, BrowserCookieStore, CookieFinder};
+//!
+//! let finder = CookieFinder::builder().with_browser(Browser::Firefox).build();
+//! let store = BrowserCookieStore::load(&finder).await?;
+//!
+//! let client = reqwest::Client::builder()
+//!     .cookie_provider(Arc::new(store))
+//!     .build()?;
+//! ```
+use cookie::time::OffsetDateTime;
+use cookie::Cookie;
+use reqwest::cookie::CookieStore;
+use reqwest::header::HeaderValue;
+use std::sync::RwLock;
+use url::Url;
+
+use crate::cookies::{domain_match, BrowserCookie, BrowserCookies};
+use crate::errors::BrowsercookieError;
+use crate::CookieFinder;
+
+/// A [`CookieStore`] sending browser cookies along with each request
+///
+/// Cookies set by servers are kept in memory only, the browser's own cookie
+/// stores are never written to.
+#[derive(Debug, Default)]
+pub struct BrowserCookieStore {
+    cookies: RwLock<BrowserCookies>,
+}
+
+impl BrowserCookieStore {
+    pub fn new(cookies: BrowserCookies) -> Self {
+        BrowserCookieStore {
+            cookies: RwLock::new(cookies),
+        }
+    }
+
+    /// Seeds a store with the cookies `finder` finds
+    pub async fn load(finder: &CookieFinder<'_>) -> Result<Self, BrowsercookieError> {
+        Ok(BrowserCookieStore::new(finder.find().await?))
+    }
+
+    /// Returns a copy of the cookies currently in the store
+    pub fn to_browser_cookies(&self) -> BrowserCookies {
+        self.cookies.read().unwrap().clone()
+    }
+
+    fn set_cookie(&self, set_cookie: &str, url: &Url, now: OffsetDateTime) {
+        // RFC 6265 5.3, leaving out the public suffix check
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return,
+        };
+        let mut cookie = match Cookie::parse(set_cookie) {
+            Ok(cookie) => cookie.into_owned(),
+            Err(_) => return,
+        };
+
+        let host_only = match cookie.domain() {
+            Some(domain) if !domain.is_empty() => {
+                if !domain_match(host, domain, false) {
+                    return;
+                }
+                false
+            }
+            _ => {
+                cookie.set_domain(String::from(host));
+                true
+            }
+        };
+        if !cookie.path().is_some_and(|p| p.starts_with('/')) {
+            cookie.set_path(default_path(url));
+        }
+        if let Some(max_age) = cookie.max_age() {
+            cookie.set_expires(now + max_age);
+        }
+
+        let mut new = BrowserCookie::new(cookie);
+        new.host_only = host_only;
+        new.creation_time = Some(now);
+        new.last_accessed = Some(now);
+
+        let mut cookies = self.cookies.write().unwrap();
+        if new.expires_datetime().is_some_and(|expires| expires <= now) {
+            // Servers delete cookies by setting them expired
+            cookies.retain(|c| !c.is_same(&new));
+            return;
+        }
+        if let Some(existing) = cookies.iter().find(|c| c.is_same(&new)) {
+            new.creation_time = existing.creation_time;
+        }
+        cookies.add(new);
+    }
+}
+
+impl From<BrowserCookies> for BrowserCookieStore {
+    fn from(cookies: BrowserCookies) -> Self {
+        BrowserCookieStore::new(cookies)
+    }
+}
+
+impl CookieStore for BrowserCookieStore {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let now = OffsetDateTime::now_utc();
+        for header in cookie_headers {
+            if let Ok(set_cookie) = header.to_str() {
+                self.set_cookie(set_cookie, url, now);
+            }
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let header = self.cookies.read().unwrap().header_for_url(url);
+        if header.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&header).ok()
+    }
+}
+
+fn default_path(url: &Url) -> String {
+    // RFC 6265 5.1.4, the request path up to its last slash
+    match url.path().rfind('/') {
+        Some(0) | None => String::from("/"),
+        Some(i) => String::from(&url.path()[..i]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_cookies(store: &BrowserCookieStore, url: &Url, headers: &[&str]) {
+        let headers: Vec<HeaderValue> = headers
+            .iter()
+            .map(|h| HeaderValue::from_str(h).unwrap())
+            .collect();
+        store.set_cookies(&mut headers.iter(), url);
+    }
+
+    fn header(store: &BrowserCookieStore, url: &str) -> Option<String> {
+        CookieStore::cookies(store, &Url::parse(url).unwrap())
+            .map(|h| String::from(h.to_str().unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_load_from_finder() {
+        let finder = CookieFinder::builder()
+            .with_browser(crate::Browser::Chrome)
+            .with_key_provider(crate::keyring::StaticPassword::new("testpassword"))
+            .build();
+        let store = BrowserCookieStore::load(&finder).await.unwrap();
+
+        assert_eq!(
+            header(&store, "https://chromehost.example/"),
+            Some(String::from("chromename=chromevalue"))
+        );
+        assert_eq!(header(&store, "https://unknown.example/"), None);
+    }
+
+    #[test]
+    fn test_set_cookies() {
+        let store = BrowserCookieStore::default();
+        let url = Url::parse("https://api.example.com/v1/users").unwrap();
+        set_cookies(
+            &store,
+            &url,
+            &[
+                "session=abc; Secure; HttpOnly",
+                "shared=1; Domain=example.com; Path=/",
+                "evil=1; Domain=other.com",
+            ],
+        );
+
+        assert_eq!(
+            header(&store, "https://api.example.com/v1/users/1"),
+            Some(String::from("session=abc; shared=1"))
+        );
+        // session is host-only, with the default path /v1
+        assert_eq!(
+            header(&store, "https://www.example.com/v1"),
+            Some(String::from("shared=1"))
+        );
+        assert_eq!(header(&store, "https://other.com/"), None);
+    }
+
+    #[test]
+    fn test_set_cookies_replaces_and_deletes() {
+        let store = BrowserCookieStore::default();
+        let url = Url::parse("https://example.com/").unwrap();
+        set_cookies(&store, &url, &["session=old"]);
+        set_cookies(&store, &url, &["session=new"]);
+
+        assert_eq!(
+            header(&store, "https://example.com/"),
+            Some(String::from("session=new"))
+        );
+
+        set_cookies(&store, &url, &["session=; Max-Age=0"]);
+
+        assert_eq!(header(&store, "https://example.com/"), None);
+        assert!(store.to_browser_cookies().is_empty());
+    }
+}